@@ -4,4 +4,5 @@ mod benchmarks;
 
 criterion_main! {
     benchmarks::tracing_bench::benches,
+    benchmarks::contention_bench::benches,
 }
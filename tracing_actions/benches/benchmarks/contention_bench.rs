@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+use criterion::{black_box, criterion_group, BenchmarkId, Criterion};
+use tracing::{metadata::LevelFilter, Dispatch, Instrument, Level};
+use tracing_actions::{span_constructor::LazySpanCache, ActionTraceSubscriber, TraceSink};
+
+struct NoSink;
+impl TraceSink for NoSink {
+    fn sink_trace(&self, _trace: &mut tracing_actions::ActionSpan) {}
+}
+
+const SPANS_PER_THREAD: u64 = 1024;
+
+/// Each thread makes spans with a child, an event and a record, so every subscriber call
+/// that touches live span storage is exercised.
+fn trace_from_threads(dispatch: &Dispatch, threads: usize, iterations: u64) -> Duration {
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                tracing::dispatcher::with_default(dispatch, || {
+                    for _ in 0..iterations * SPANS_PER_THREAD {
+                        let span = tracing::span!(Level::INFO, "bench");
+                        let _guard = black_box(span.enter());
+                        span.record("some", 42);
+                        tracing::info!("an event");
+                        {
+                            let child_span = tracing::span!(parent: &span, Level::DEBUG, "subspan");
+                            let _a = async {}.instrument(child_span);
+                        }
+                    }
+                })
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Contended traces");
+    let threads = std::thread::available_parallelism()
+        .map(|cores| cores.get())
+        .unwrap_or(8)
+        .max(8);
+
+    for (name, shards) in [("single lock", Some(1)), ("sharded", None)] {
+        let subscriber =
+            ActionTraceSubscriber::new(LevelFilter::DEBUG, NoSink, LazySpanCache::default());
+        let subscriber = match shards {
+            Some(shards) => subscriber.with_span_store_shards(shards),
+            None => subscriber,
+        };
+        let dispatch = Dispatch::new(subscriber);
+
+        group.bench_with_input(
+            BenchmarkId::new(name, threads),
+            &threads,
+            |bencher, threads| {
+                bencher
+                    .iter_custom(|iterations| trace_from_threads(&dispatch, *threads, iterations))
+            },
+        );
+    }
+}
+
+criterion_group!(benches, contention);
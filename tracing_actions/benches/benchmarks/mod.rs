@@ -1 +1,2 @@
+pub mod contention_bench;
 pub mod tracing_bench;
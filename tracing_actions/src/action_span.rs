@@ -1,30 +1,90 @@
-use std::{collections::HashMap, time::SystemTime};
-
-use tracing::{field::Visit, span::Attributes, Metadata};
+use std::{
+    any::type_name,
+    backtrace::{Backtrace, BacktraceStatus},
+    collections::HashMap,
+    error::Error,
+    fmt::Write,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use tracing::{
+    field::Visit,
+    span::{Attributes, Record},
+    Metadata,
+};
+
+use crate::{
+    allocations::Allocations,
+    static_metadata::{EXCEPTION_EVENT, SLOW_POLL_EVENT},
+    SpanContext, SpanLimits,
+};
 
 pub trait Resettable {
     fn reset(&mut self);
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Set a span's kind with the reserved `otel.kind` field, like
+/// `tracing::info_span!("fetch user", otel.kind = "client")`.
+///
+/// Root spans are `Server` spans by default, and their children are `Internal`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum TraceKind {
     Client,
     Server,
+    #[default]
+    Internal,
+    Producer,
+    Consumer,
 }
-impl Default for TraceKind {
-    fn default() -> Self {
-        Self::Server
+
+impl FromStr for TraceKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "client" => Ok(Self::Client),
+            "server" => Ok(Self::Server),
+            "internal" => Ok(Self::Internal),
+            "producer" => Ok(Self::Producer),
+            "consumer" => Ok(Self::Consumer),
+            _ => Err(()),
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Fields that configure the span instead of becoming attributes.
+const OTEL_KIND: &str = "otel.kind";
+const OTEL_NAME: &str = "otel.name";
+const OTEL_STATUS_CODE: &str = "otel.status_code";
+const OTEL_STATUS_MESSAGE: &str = "otel.status_message";
+/// A remote parent's W3C `traceparent`, read before the span starts so the sampler can follow it.
+pub(crate) const OTEL_PARENT: &str = "otel.parent";
+
+/// A span's status, as in opentelemetry.
+///
+/// Spans start `Unset`. Recording an error upgrades them to `Error`, and the reserved
+/// `otel.status_code` field sets the status explicitly, like
+/// `tracing::info_span!("request", otel.status_code = "error", otel.status_message = "503 Service Unavailable")`.
+/// `Ok` is final: nothing changes the status after it is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum SpanStatus {
+    #[default]
+    Unset,
     Ok,
     Error,
 }
-impl Default for SpanStatus {
-    fn default() -> Self {
-        Self::Ok
+
+impl FromStr for SpanStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "unset" => Ok(Self::Unset),
+            "ok" => Ok(Self::Ok),
+            "error" => Ok(Self::Error),
+            _ => Err(()),
+        }
     }
 }
 
@@ -49,9 +109,21 @@ pub struct ActionSpan {
     /// field must be empty.
     pub parent_span_id: Option<[u8; 8]>,
 
+    /// True when the parent span is in another process, from the `otel.parent` field or
+    /// `ActionSpanExt::set_parent()`.
+    pub parent_is_remote: bool,
+
+    /// W3C trace flags. Spans without `SpanContext::SAMPLED` are not sent to your sink.
+    pub trace_flags: u8,
+
     /// A description of the span, with its name inside.
     pub metadata: Option<&'static Metadata<'static>>,
 
+    /// Replaces the metadata's static name when the reserved `otel.name` field is recorded,
+    /// like `tracing::info_span!("request", otel.name = %format!("{method} {route}"))`.
+    /// Use `name()` for the span's name.
+    pub name_override: Option<String>,
+
     /// Distinguishes between spans generated in a particular context. For example,
     /// two spans with the same name may be distinguished using `CLIENT` (caller)
     /// and `SERVER` (callee) to identify queueing latency associated with the span.
@@ -84,7 +156,60 @@ pub struct ActionSpan {
     /// events is a collection of Event items.
     pub events: Vec<ActionEvent>,
 
+    /// links is a collection of Links, which are references from this span to a span
+    /// in the same or different trace. `follows_from` relationships are recorded here.
+    pub links: Vec<ActionLink>,
+
     pub status: SpanStatus,
+
+    /// Why the span failed. Only meaningful with `SpanStatus::Error`.
+    pub status_message: String,
+
+    /// How much this span may hold. Set by the subscriber when the span starts.
+    pub limits: SpanLimits,
+
+    /// Add a backtrace to the exception events of errors recorded on this span. Set by the
+    /// subscriber when the span starts.
+    pub capture_backtraces: bool,
+
+    /// Attributes that were dropped because of `limits`.
+    pub dropped_attributes_count: u32,
+
+    /// Events that were dropped because of `limits`.
+    pub dropped_events_count: u32,
+
+    /// Links that were dropped because of `limits`.
+    pub dropped_links_count: u32,
+
+    /// Time spent inside the span, between entering and exiting it. For a future, this is
+    /// the time spent polling it.
+    pub busy: Duration,
+
+    /// Time the span was open but not entered, like a future waiting on I/O. Set when the span ends.
+    pub idle: Duration,
+
+    /// How many times the span was entered. For a future, this is how many times it was polled.
+    pub enter_count: u64,
+    /// The longest time the span stayed entered at once. For a future, this is its longest poll.
+    pub longest_poll: Duration,
+    /// Set when any poll took longer than the slow poll threshold. Each slow poll also adds a
+    /// `slow poll` warning event.
+    pub slow_poll: bool,
+
+    /// CPU time this span's thread used while inside it. Only measured when you turn it on
+    /// with `with_cpu_time()`, on Linux.
+    pub cpu_time: Option<Duration>,
+
+    /// Heap allocations made while this was the innermost entered span. Only counted when
+    /// the `CountingAllocator` is installed and you turn it on with `with_allocation_counts()`.
+    pub allocations: u64,
+    /// Bytes requested by those allocations. A reallocation counts only what it grew by, and
+    /// memory freed in the span is not subtracted.
+    pub allocated_bytes: u64,
+
+    /// Set when the span was never closed, and was force-closed because it was too old or
+    /// too many spans were open. Its end time is when it was evicted.
+    pub truncated: bool,
 }
 
 impl Default for ActionSpan {
@@ -95,13 +220,32 @@ impl Default for ActionSpan {
             span_id: Default::default(),
             trace_state: Default::default(),
             parent_span_id: Default::default(),
+            parent_is_remote: false,
+            trace_flags: 0,
             metadata: Default::default(),
+            name_override: None,
             kind: Default::default(),
-            start: SystemTime::now(),
-            end: SystemTime::now(),
+            start: SystemTime::UNIX_EPOCH,
+            end: SystemTime::UNIX_EPOCH,
             attributes: Default::default(),
             events: Default::default(),
+            links: Default::default(),
             status: Default::default(),
+            status_message: Default::default(),
+            limits: Default::default(),
+            capture_backtraces: false,
+            dropped_attributes_count: 0,
+            dropped_events_count: 0,
+            dropped_links_count: 0,
+            busy: Duration::ZERO,
+            idle: Duration::ZERO,
+            enter_count: 0,
+            longest_poll: Duration::ZERO,
+            slow_poll: false,
+            cpu_time: None,
+            allocations: 0,
+            allocated_bytes: 0,
+            truncated: false,
         }
     }
 }
@@ -113,70 +257,478 @@ impl Resettable for ActionSpan {
         self.span_id.fill(0);
         self.trace_state = Default::default();
         self.parent_span_id = None;
+        self.parent_is_remote = false;
+        self.trace_flags = 0;
         self.metadata = Default::default();
+        self.name_override = None;
         self.kind = Default::default();
         self.attributes.clear();
         self.events.clear();
+        self.links.clear();
         self.status = Default::default();
+        self.status_message.clear();
+        self.dropped_attributes_count = 0;
+        self.dropped_events_count = 0;
+        self.dropped_links_count = 0;
+        self.busy = Duration::ZERO;
+        self.idle = Duration::ZERO;
+        self.enter_count = 0;
+        self.longest_poll = Duration::ZERO;
+        self.slow_poll = false;
+        self.cpu_time = None;
+        self.allocations = 0;
+        self.allocated_bytes = 0;
+        self.truncated = false;
     }
 }
 
 impl ActionSpan {
-    pub fn start_root(&mut self, attributes: &Attributes) {
-        self.trace_id = rand::random();
-        self.span_id = rand::random();
+    pub fn start_root(
+        &mut self,
+        attributes: &Attributes,
+        trace_id: [u8; 16],
+        span_id: [u8; 8],
+        start: SystemTime,
+    ) {
+        self.trace_id = trace_id;
+        self.span_id = span_id;
+        self.trace_flags = SpanContext::SAMPLED;
+        self.kind = TraceKind::Server;
 
-        self.start = SystemTime::now();
+        self.start = start;
 
         self.attach_attributes(attributes);
     }
 
+    /// Children join their parent's trace, and inherit its trace state and flags.
     pub fn start_child(
         &mut self,
         attributes: &Attributes,
-        trace_id: &[u8; 16],
-        parent_span_id: &[u8; 8],
+        parent: &SpanContext,
+        span_id: [u8; 8],
+        start: SystemTime,
     ) {
-        self.trace_id.copy_from_slice(trace_id);
-        self.span_id = rand::random();
-        self.parent_span_id = Some(*parent_span_id); // We can use the Copy trait here
+        self.trace_id = parent.trace_id;
+        self.span_id = span_id;
+        self.parent_span_id = Some(parent.span_id);
+        self.parent_is_remote = parent.is_remote;
+        self.trace_flags = parent.trace_flags;
+        self.trace_state.clone_from(&parent.trace_state);
 
-        self.start = SystemTime::now();
+        self.start = start;
 
         self.attach_attributes(attributes);
     }
 
     pub fn end(&mut self) {
-        self.end = SystemTime::now();
+        self.end_at(SystemTime::now())
+    }
+
+    /// Like `end()`, with the end time from your own clock.
+    pub fn end_at(&mut self, end: SystemTime) {
+        self.end = end;
+        let open = end.duration_since(self.start).unwrap_or_default();
+        self.idle = open.saturating_sub(self.busy);
+    }
+
+    /// Count a visit to this span, from entering it at `entered_at` to exiting it at `exited_at`.
+    /// A visit longer than `slow_poll_threshold` marks the span as a slow poller.
+    pub fn exited(
+        &mut self,
+        entered_at: SystemTime,
+        exited_at: SystemTime,
+        slow_poll_threshold: Option<Duration>,
+    ) {
+        let poll = exited_at.duration_since(entered_at).unwrap_or_default();
+        self.busy += poll;
+        self.enter_count += 1;
+        self.longest_poll = self.longest_poll.max(poll);
+        if let Some(threshold) = slow_poll_threshold {
+            if threshold < poll {
+                self.slow_poll = true;
+                let slow_poll = ActionEvent::slow_poll(poll, threshold, &self.limits, exited_at);
+                self.add_event(slow_poll);
+            }
+        }
+    }
+
+    /// Count CPU time used during a visit to this span.
+    pub fn add_cpu_time(&mut self, cpu_time: Duration) {
+        *self.cpu_time.get_or_insert(Duration::ZERO) += cpu_time;
+    }
+
+    pub(crate) fn add_allocations(&mut self, allocations: Allocations) {
+        self.allocations += allocations.count;
+        self.allocated_bytes += allocations.bytes;
+    }
+
+    /// Record fields on this span, like `tracing::Span::record()`. Recorded errors become
+    /// exception events at `now`.
+    pub fn record(&mut self, values: &Record<'_>, now: SystemTime) {
+        values.record(&mut SpanRecorder { span: self, now })
+    }
+
+    /// Move this span into the trace of a span in another process.
+    ///
+    /// The trace flags are left for the sampler to decide.
+    pub fn set_remote_parent(&mut self, parent: &SpanContext) {
+        self.trace_id = parent.trace_id;
+        self.parent_span_id = Some(parent.span_id);
+        self.parent_is_remote = true;
+        self.trace_state.clone_from(&parent.trace_state);
+    }
+
+    /// Attributes past `limits.max_attributes_per_span` are dropped, unless they replace one already here.
+    pub fn add_attribute(&mut self, key: &'static str, value: AttributeValue) {
+        self.limits.insert_attribute(
+            self.limits.max_attributes_per_span,
+            &mut self.attributes,
+            &mut self.dropped_attributes_count,
+            key,
+            value,
+        );
+    }
+
+    /// Events past `limits.max_events_per_span` are dropped.
+    pub fn add_event(&mut self, event: ActionEvent) {
+        if !self.drop_event_if_full() {
+            self.events.push(event);
+        }
+    }
+
+    /// Check the event limit before building an event: when the span is full, the event
+    /// is counted as dropped and this returns true.
+    pub(crate) fn drop_event_if_full(&mut self) -> bool {
+        let full = self.limits.max_events_per_span <= self.events.len();
+        if full {
+            self.dropped_events_count += 1;
+        }
+        full
+    }
+
+    /// Add an `exception` event for `error`, and fail the span if its status is unset.
+    /// `error_type` is the error's type name, for `exception.type`.
+    pub(crate) fn record_exception(
+        &mut self,
+        error: &(dyn Error + 'static),
+        error_type: Option<&str>,
+        now: SystemTime,
+    ) {
+        if !self.drop_event_if_full() {
+            let exception = ActionEvent::exception(
+                error,
+                error_type,
+                &self.limits,
+                now,
+                self.capture_backtraces,
+            );
+            self.events.push(exception);
+        }
+        // Recording an error fails an unset span. The first error is the reason, unless one was given.
+        if self.status == SpanStatus::Unset {
+            self.status = SpanStatus::Error;
+            if self.status_message.is_empty() {
+                self.status_message = error.to_string();
+            }
+        }
+    }
+
+    /// Links past `limits.max_links_per_span` are dropped, and so are their attributes past
+    /// `limits.max_attributes_per_link`.
+    pub fn add_link(&mut self, mut link: ActionLink) {
+        if self.limits.max_links_per_span <= self.links.len() {
+            self.dropped_links_count += 1;
+        } else {
+            self.limits.limit_attributes(
+                self.limits.max_attributes_per_link,
+                &mut link.attributes,
+                &mut link.dropped_attributes_count,
+            );
+            self.links.push(link);
+        }
+    }
+
+    /// `Ok` is final, and `Unset` does not undo a status.
+    pub fn set_status(&mut self, status: SpanStatus) {
+        match (self.status, status) {
+            (SpanStatus::Ok, _) | (_, SpanStatus::Unset) => (),
+            (_, SpanStatus::Ok) => {
+                self.status = SpanStatus::Ok;
+                self.status_message.clear();
+            }
+            (_, SpanStatus::Error) => self.status = SpanStatus::Error,
+        }
+    }
+
+    /// The `otel.name` field when it was recorded, or else the span's static name.
+    pub fn name(&self) -> &str {
+        match (&self.name_override, self.metadata) {
+            (Some(name), _) => name,
+            (None, Some(metadata)) => metadata.name(),
+            (None, None) => "unknown",
+        }
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.trace_flags & SpanContext::SAMPLED == SpanContext::SAMPLED
+    }
+
+    /// This span's identity, for propagating to other processes.
+    pub fn span_context(&self) -> SpanContext {
+        SpanContext {
+            trace_id: self.trace_id,
+            span_id: self.span_id,
+            trace_flags: self.trace_flags,
+            trace_state: self.trace_state.clone(),
+            is_remote: false,
+        }
+    }
+
+    /// Returns false when `name` is not a reserved field.
+    fn record_reserved_field(&mut self, name: &str, value: &str) -> bool {
+        match name {
+            OTEL_KIND => match value.parse() {
+                Ok(kind) => self.kind = kind,
+                Err(_) => log::debug!("unknown span kind {value} - not setting it"),
+            },
+            OTEL_NAME => self.name_override = Some(value.to_owned()),
+            OTEL_STATUS_CODE => match value.parse() {
+                Ok(status) => self.set_status(status),
+                Err(_) => log::debug!("unknown span status {value} - not setting it"),
+            },
+            OTEL_STATUS_MESSAGE => {
+                if self.status != SpanStatus::Ok {
+                    self.status_message = value.to_owned()
+                }
+            }
+            // Already applied when the span was started.
+            OTEL_PARENT => (),
+            _ => return false,
+        }
+        true
+    }
+
+    fn recorder(&mut self) -> SpanRecorder<'_> {
+        SpanRecorder {
+            span: self,
+            now: SystemTime::now(),
+        }
     }
 
     fn attach_attributes(&mut self, attributes: &Attributes) {
         let metadata = attributes.metadata();
         self.metadata = Some(metadata);
-        attributes.values().record(self)
+        let now = self.start;
+        attributes
+            .values()
+            .record(&mut SpanRecorder { span: self, now })
     }
 }
 
+/// Records fields on a span. Errors become exception events at `now`.
+struct SpanRecorder<'a> {
+    span: &'a mut ActionSpan,
+    now: SystemTime,
+}
+
 #[derive(Debug, Clone)]
 pub struct ActionEvent {
     pub metadata: &'static Metadata<'static>,
     pub attributes: HashMap<&'static str, AttributeValue>,
     pub timestamp: SystemTime,
+    /// Attributes that were dropped because of the span limits.
+    pub dropped_attributes_count: u32,
 }
 
+/// Records an event's fields within the span limits.
+struct LimitedEvent<'a> {
+    event: &'a mut ActionEvent,
+    limits: &'a SpanLimits,
+}
+
+/// An event at the current wall clock time, with the default span limits.
 impl<'a> From<&'a tracing::Event<'a>> for ActionEvent {
     fn from(event: &'a tracing::Event<'a>) -> Self {
-        let mut selff = Self {
+        Self::new(event, &SpanLimits::default(), SystemTime::now())
+    }
+}
+
+impl ActionEvent {
+    pub(crate) fn new(
+        event: &tracing::Event<'_>,
+        limits: &SpanLimits,
+        timestamp: SystemTime,
+    ) -> Self {
+        let mut action_event = Self {
             metadata: event.metadata(),
             attributes: HashMap::new(),
-            timestamp: SystemTime::now(),
+            timestamp,
+            dropped_attributes_count: 0,
         };
-        event.record(&mut selff);
-        selff
+        event.record(&mut LimitedEvent {
+            event: &mut action_event,
+            limits,
+        });
+        action_event
+    }
+
+    pub(crate) fn add_attribute(
+        &mut self,
+        key: &'static str,
+        value: AttributeValue,
+        limits: &SpanLimits,
+    ) {
+        limits.insert_attribute(
+            limits.max_attributes_per_event,
+            &mut self.attributes,
+            &mut self.dropped_attributes_count,
+            key,
+            value,
+        );
+    }
+
+    /// An opentelemetry `exception` event, with `exception.type`, `exception.message` and `exception.stacktrace`.
+    /// `exception.type` is left out when `error_type` is None.
+    ///
+    /// The stacktrace lists the error's `source()` chain, and then a backtrace of where the error
+    /// was recorded with `capture_backtrace`. Capturing a backtrace is slow.
+    pub(crate) fn exception(
+        error: &(dyn Error + 'static),
+        error_type: Option<&str>,
+        limits: &SpanLimits,
+        timestamp: SystemTime,
+        capture_backtrace: bool,
+    ) -> Self {
+        let mut stacktrace = error.to_string();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            let _ = write!(stacktrace, "\ncaused by: {cause}");
+            source = cause.source();
+        }
+        if capture_backtrace {
+            let backtrace = Backtrace::force_capture();
+            if backtrace.status() == BacktraceStatus::Captured {
+                let _ = write!(stacktrace, "\n\n{backtrace}");
+            }
+        }
+
+        let mut exception = Self {
+            metadata: &EXCEPTION_EVENT,
+            attributes: HashMap::new(),
+            timestamp,
+            dropped_attributes_count: 0,
+        };
+        if let Some(error_type) = error_type {
+            exception.add_attribute(
+                "exception.type",
+                AttributeValue::String(error_type.to_string()),
+                limits,
+            );
+        }
+        for (key, value) in [
+            ("exception.message", error.to_string()),
+            ("exception.stacktrace", stacktrace),
+        ] {
+            exception.add_attribute(key, AttributeValue::String(value), limits);
+        }
+        exception
+    }
+
+    pub(crate) fn slow_poll(
+        poll: Duration,
+        threshold: Duration,
+        limits: &SpanLimits,
+        timestamp: SystemTime,
+    ) -> Self {
+        let mut slow_poll = Self {
+            metadata: &SLOW_POLL_EVENT,
+            attributes: HashMap::new(),
+            timestamp,
+            dropped_attributes_count: 0,
+        };
+        for (key, value) in [("poll_ns", poll), ("threshold_ns", threshold)] {
+            slow_poll.add_attribute(key, AttributeValue::U64(value.as_nanos() as u64), limits);
+        }
+        slow_poll
     }
 }
 
-#[derive(Debug, Clone)]
+/// A `dyn Error` does not know its type name, so only the standard library's errors get one.
+/// Record other errors with `ActionSpanExt::record_exception()` to name them.
+fn known_error_type(error: &(dyn Error + 'static)) -> Option<&'static str> {
+    fn is<E: Error + 'static>(error: &(dyn Error + 'static)) -> Option<&'static str> {
+        error.is::<E>().then(type_name::<E>)
+    }
+    const KNOWN: &[fn(&(dyn Error + 'static)) -> Option<&'static str>] = &[
+        is::<std::io::Error>,
+        is::<std::fmt::Error>,
+        is::<std::num::ParseIntError>,
+        is::<std::num::ParseFloatError>,
+        is::<std::num::TryFromIntError>,
+        is::<std::str::ParseBoolError>,
+        is::<std::str::Utf8Error>,
+        is::<std::string::FromUtf8Error>,
+        is::<std::net::AddrParseError>,
+        is::<std::time::SystemTimeError>,
+    ];
+    KNOWN.iter().find_map(|known| known(error))
+}
+
+/// A pointer from the current span to another span in the same trace or in a
+/// different trace. For example, this can be used in batching operations,
+/// where a single batch handler processes multiple requests from different
+/// traces or when the handler receives a request from a different project.
+#[derive(Debug, Clone, Default)]
+pub struct ActionLink {
+    /// A unique identifier of a trace that this linked span is part of. The ID is a
+    /// 16-byte array.
+    pub trace_id: [u8; 16],
+
+    /// A unique identifier for the linked span. The ID is an 8-byte array.
+    pub span_id: [u8; 8],
+
+    /// The trace_state associated with the link, in w3c-trace-context format.
+    pub trace_state: String,
+
+    /// attributes is a collection of attribute key/value pairs on the link.
+    pub attributes: HashMap<&'static str, AttributeValue>,
+
+    /// Attributes that were dropped because of the span limits.
+    pub dropped_attributes_count: u32,
+}
+
+impl ActionLink {
+    /// A link to the span `span_id` in the trace `trace_id`, like one you extracted from a message header.
+    pub fn new(trace_id: [u8; 16], span_id: [u8; 8]) -> Self {
+        Self {
+            trace_id,
+            span_id,
+            ..Default::default()
+        }
+    }
+
+    /// A link to another span you have on hand.
+    pub fn to_span(span: &ActionSpan) -> Self {
+        Self {
+            trace_id: span.trace_id,
+            span_id: span.span_id,
+            trace_state: span.trace_state.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_trace_state(mut self, trace_state: impl Into<String>) -> Self {
+        self.trace_state = trace_state.into();
+        self
+    }
+
+    pub fn with_attribute(mut self, key: &'static str, value: AttributeValue) -> Self {
+        self.attributes.insert(key, value);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum AttributeValue {
     String(String),
     F64(f64),
@@ -188,45 +740,93 @@ pub enum AttributeValue {
     Error(String),
 }
 
+/// Records fields like `ActionSpan::record()`, with errors at the current wall clock time.
 impl Visit for ActionSpan {
     fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
-        self.attributes
-            .insert(field.name(), AttributeValue::String(format!("{value:?}")));
+        self.recorder().record_debug(field, value)
     }
 
     fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
-        self.attributes
-            .insert(field.name(), AttributeValue::F64(value));
+        self.recorder().record_f64(field, value)
     }
 
     fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
-        self.attributes
-            .insert(field.name(), AttributeValue::I64(value));
+        self.recorder().record_i64(field, value)
     }
 
     fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
-        self.attributes
-            .insert(field.name(), AttributeValue::U64(value));
+        self.recorder().record_u64(field, value)
     }
 
     fn record_i128(&mut self, field: &tracing::field::Field, value: i128) {
-        self.attributes
-            .insert(field.name(), AttributeValue::I128(value));
+        self.recorder().record_i128(field, value)
     }
 
     fn record_u128(&mut self, field: &tracing::field::Field, value: u128) {
-        self.attributes
-            .insert(field.name(), AttributeValue::U128(value));
+        self.recorder().record_u128(field, value)
     }
 
     fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
-        self.attributes
-            .insert(field.name(), AttributeValue::Bool(value));
+        self.recorder().record_bool(field, value)
     }
 
     fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
-        self.attributes
-            .insert(field.name(), AttributeValue::String(value.to_owned()));
+        self.recorder().record_str(field, value)
+    }
+
+    fn record_error(
+        &mut self,
+        field: &tracing::field::Field,
+        value: &(dyn std::error::Error + 'static),
+    ) {
+        self.recorder().record_error(field, value)
+    }
+}
+
+impl<'a> Visit for SpanRecorder<'a> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        let value = format!("{value:?}");
+        if !self.span.record_reserved_field(field.name(), &value) {
+            self.span
+                .add_attribute(field.name(), AttributeValue::String(value));
+        }
+    }
+
+    fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
+        self.span
+            .add_attribute(field.name(), AttributeValue::F64(value));
+    }
+
+    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+        self.span
+            .add_attribute(field.name(), AttributeValue::I64(value));
+    }
+
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        self.span
+            .add_attribute(field.name(), AttributeValue::U64(value));
+    }
+
+    fn record_i128(&mut self, field: &tracing::field::Field, value: i128) {
+        self.span
+            .add_attribute(field.name(), AttributeValue::I128(value));
+    }
+
+    fn record_u128(&mut self, field: &tracing::field::Field, value: u128) {
+        self.span
+            .add_attribute(field.name(), AttributeValue::U128(value));
+    }
+
+    fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
+        self.span
+            .add_attribute(field.name(), AttributeValue::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        if !self.span.record_reserved_field(field.name(), value) {
+            self.span
+                .add_attribute(field.name(), AttributeValue::String(value.to_owned()));
+        }
     }
 
     fn record_error(
@@ -234,52 +834,58 @@ impl Visit for ActionSpan {
         field: &tracing::field::Field,
         value: &(dyn std::error::Error + 'static),
     ) {
-        // This defaults to ok. If you want to make a span error, you just record at least 1 error on the span.
-        self.status = SpanStatus::Error;
-        self.attributes
-            .insert(field.name(), AttributeValue::Error(format!("{value:?}")));
+        self.span
+            .record_exception(value, known_error_type(value), self.now);
+        self.span
+            .add_attribute(field.name(), AttributeValue::Error(format!("{value:?}")));
     }
 }
 
-impl Visit for ActionEvent {
+impl<'a> Visit for LimitedEvent<'a> {
     fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
-        self.attributes
-            .insert(field.name(), AttributeValue::String(format!("{value:?}")));
+        self.event.add_attribute(
+            field.name(),
+            AttributeValue::String(format!("{value:?}")),
+            self.limits,
+        );
     }
 
     fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
-        self.attributes
-            .insert(field.name(), AttributeValue::F64(value));
+        self.event
+            .add_attribute(field.name(), AttributeValue::F64(value), self.limits);
     }
 
     fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
-        self.attributes
-            .insert(field.name(), AttributeValue::I64(value));
+        self.event
+            .add_attribute(field.name(), AttributeValue::I64(value), self.limits);
     }
 
     fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
-        self.attributes
-            .insert(field.name(), AttributeValue::U64(value));
+        self.event
+            .add_attribute(field.name(), AttributeValue::U64(value), self.limits);
     }
 
     fn record_i128(&mut self, field: &tracing::field::Field, value: i128) {
-        self.attributes
-            .insert(field.name(), AttributeValue::I128(value));
+        self.event
+            .add_attribute(field.name(), AttributeValue::I128(value), self.limits);
     }
 
     fn record_u128(&mut self, field: &tracing::field::Field, value: u128) {
-        self.attributes
-            .insert(field.name(), AttributeValue::U128(value));
+        self.event
+            .add_attribute(field.name(), AttributeValue::U128(value), self.limits);
     }
 
     fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
-        self.attributes
-            .insert(field.name(), AttributeValue::Bool(value));
+        self.event
+            .add_attribute(field.name(), AttributeValue::Bool(value), self.limits);
     }
 
     fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
-        self.attributes
-            .insert(field.name(), AttributeValue::String(value.to_owned()));
+        self.event.add_attribute(
+            field.name(),
+            AttributeValue::String(value.to_owned()),
+            self.limits,
+        );
     }
 
     fn record_error(
@@ -287,7 +893,10 @@ impl Visit for ActionEvent {
         field: &tracing::field::Field,
         value: &(dyn std::error::Error + 'static),
     ) {
-        self.attributes
-            .insert(field.name(), AttributeValue::Error(format!("{value:?}")));
+        self.event.add_attribute(
+            field.name(),
+            AttributeValue::Error(format!("{value:?}")),
+            self.limits,
+        );
     }
 }
@@ -0,0 +1,578 @@
+use std::{
+    any::TypeId,
+    time::{Duration, SystemTime},
+};
+
+use tracing::{span, Dispatch, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::{
+    action_span::{ActionEvent, Resettable},
+    clock::{Clock, SystemClock},
+    cpu_time,
+    id_generator::{IdGenerator, ThreadLocalRandom},
+    propagation,
+    sampler::{self, AlwaysOn, Sampler, SamplingDecision, SamplingParameters},
+    source_attributes::SourceAttributes,
+    span_constructor::SpanConstructor,
+    span_ext::WithActionSpan,
+    ActionLink, ActionSpan, EventSink, SpanContext, SpanLimits, TraceSink,
+};
+
+/// A `tracing_subscriber::Layer` that records ActionSpans.
+///
+/// This is the composable sibling of `ActionTraceSubscriber`. Live ActionSpans are kept
+/// in the registry's span extensions, so you can stack this layer with `fmt`, `EnvFilter`
+/// or whatever else your application uses, and still receive the same spans in your sink.
+///
+/// Some features are only in `ActionTraceSubscriber`:
+/// * Allocation counts, which charge the innermost entered span on each thread. The subscriber
+///   keeps that stack itself; the layer does not.
+/// * Evicting leaked spans with `with_max_span_age()` and `with_max_live_spans()`. Here the
+///   registry owns the spans, and closes them when their last handle is dropped.
+/// * `stats()`. The layer keeps no counters of its own; ask your sink for its stats.
+///
+/// ```rust
+/// use tracing_subscriber::prelude::*;
+///
+/// struct NoSink;
+/// impl tracing_actions::TraceSink for NoSink {
+///     fn sink_trace(&self, _trace: &mut tracing_actions::ActionSpan) {}
+/// }
+///
+/// let subscriber = tracing_subscriber::registry().with(tracing_actions::ActionTraceLayer::new(
+///     NoSink,
+///     tracing_actions::span_constructor::LazySpanCache::default(),
+/// ));
+/// tracing::subscriber::set_global_default(subscriber)
+///     .expect("I should be able to set the global trace subscriber");
+/// ```
+pub struct ActionTraceLayer<Sink, SpanConstructor> {
+    span_sink: Sink,
+    span_constructor: SpanConstructor,
+    action_span_access: WithActionSpan,
+    orphan_event_sink: Option<Box<dyn EventSink + Send + Sync>>,
+    sampler: Box<dyn Sampler + Send + Sync>,
+    span_limits: SpanLimits,
+    id_generator: Box<dyn IdGenerator + Send + Sync>,
+    clock: Box<dyn Clock + Send + Sync>,
+    measure_cpu_time: bool,
+    capture_backtraces: bool,
+    slow_poll_threshold: Option<Duration>,
+    source_attributes: SourceAttributes,
+}
+
+impl<Sink: TraceSink, TSpanConstructor: SpanConstructor> ActionTraceLayer<Sink, TSpanConstructor> {
+    pub fn new(sink: Sink, span_constructor: TSpanConstructor) -> Self {
+        Self {
+            span_sink: sink,
+            span_constructor,
+            // Replaced in on_layer, when we learn the registry's type
+            action_span_access: WithActionSpan::new(
+                |_, _, _| {},
+                |_, _, _| {},
+                |_| SystemTime::now(),
+            ),
+            orphan_event_sink: None,
+            sampler: Box::new(AlwaysOn),
+            span_limits: Default::default(),
+            id_generator: Box::new(ThreadLocalRandom),
+            clock: Box::new(SystemClock),
+            measure_cpu_time: false,
+            capture_backtraces: false,
+            slow_poll_threshold: None,
+            source_attributes: Default::default(),
+        }
+    }
+
+    /// Take span and event timestamps from `clock`. By default this is the `SystemClock`. Use a
+    /// `MonotonicClock` if your system clock gets stepped.
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Send events that have no span to `sink`. Without one, those events are dropped.
+    pub fn with_orphan_event_sink(mut self, sink: impl EventSink + Send + Sync + 'static) -> Self {
+        self.orphan_event_sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Decide which new traces to record with `sampler`. By default every trace is recorded.
+    ///
+    /// Unsampled spans are still in the registry for your other layers, without an ActionSpan.
+    pub fn with_sampler(mut self, sampler: impl Sampler + Send + Sync + 'static) -> Self {
+        self.sampler = Box::new(sampler);
+        self
+    }
+
+    /// Make trace and span ids with `id_generator`. By default ids are random.
+    pub fn with_id_generator(
+        mut self,
+        id_generator: impl IdGenerator + Send + Sync + 'static,
+    ) -> Self {
+        self.id_generator = Box::new(id_generator);
+        self
+    }
+
+    /// Cap the attributes, events and links each span can hold. What goes over is dropped and counted.
+    pub fn with_span_limits(mut self, span_limits: SpanLimits) -> Self {
+        self.span_limits = span_limits;
+        self
+    }
+
+    /// Measure the CPU time spent inside each span, into `ActionSpan::cpu_time`. Off by default.
+    /// Only Linux is supported; elsewhere this does nothing.
+    pub fn with_cpu_time(mut self, measure_cpu_time: bool) -> Self {
+        self.measure_cpu_time = measure_cpu_time;
+        self
+    }
+
+    /// Add a backtrace of where each error was recorded to its `exception.stacktrace`.
+    /// Capturing a backtrace is slow, so this is off by default.
+    pub fn with_backtraces(mut self, capture_backtraces: bool) -> Self {
+        self.capture_backtraces = capture_backtraces;
+        self
+    }
+
+    /// Flag spans that stay entered longer than `threshold` at once, like 10ms. For an
+    /// instrumented future each enter is a poll. Slow polls also add a `slow poll` warning event.
+    pub fn with_slow_poll_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_poll_threshold = Some(threshold);
+        self
+    }
+
+    /// Add `code.filepath`, `code.lineno`, `code.namespace` and `target` attributes to spans and
+    /// events, from their `tracing` metadata. Off by default.
+    pub fn with_code_attributes(mut self, enabled: bool) -> Self {
+        self.source_attributes.code = enabled;
+        self
+    }
+
+    /// Add `thread.id` and `thread.name` attributes to spans and events, for the thread that
+    /// started or recorded them. Thread ids are numbered by tracing-actions. Off by default.
+    pub fn with_thread_attributes(mut self, enabled: bool) -> Self {
+        self.source_attributes.thread = enabled;
+        self
+    }
+}
+
+/// When a span was last entered, and the thread's cpu time then if it is measured.
+/// Kept next to its ActionSpan in the registry.
+struct EnteredAt(SystemTime, Option<Duration>);
+
+/// Marks a span the sampler dropped, in place of its ActionSpan, so its children are dropped too.
+struct Unsampled;
+
+fn access_action_span<S>(
+    dispatch: &Dispatch,
+    id: &span::Id,
+    use_it: &mut dyn FnMut(&mut ActionSpan),
+) where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    let span = dispatch
+        .downcast_ref::<S>()
+        .and_then(|registry| registry.span(id));
+    if let Some(span) = span {
+        if let Some(action_span) = span.extensions_mut().get_mut::<ActionSpan>() {
+            use_it(action_span)
+        }
+    }
+}
+
+impl<Sink, TSpanConstructor> ActionTraceLayer<Sink, TSpanConstructor>
+where
+    Sink: TraceSink + 'static,
+    TSpanConstructor: SpanConstructor + 'static,
+{
+    /// Like the subscriber, the sampler decides again with the remote parent. Spans that were
+    /// not sampled have no ActionSpan to move.
+    fn set_remote_parent<S>(dispatch: &Dispatch, id: &span::Id, parent: &SpanContext)
+    where
+        S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+    {
+        if let Some(layer) = dispatch.downcast_ref::<Self>() {
+            access_action_span::<S>(dispatch, id, &mut |action_span| {
+                sampler::set_remote_parent(layer.sampler.as_ref(), action_span, parent)
+            });
+        }
+    }
+
+    fn new_action_span(&self) -> ActionSpan {
+        let mut action_span = self.span_constructor.new_span();
+        action_span.limits = self.span_limits;
+        action_span.capture_backtraces = self.capture_backtraces;
+        action_span
+    }
+
+    fn now(dispatch: &Dispatch) -> SystemTime {
+        dispatch
+            .downcast_ref::<Self>()
+            .map(|layer| layer.clock.now())
+            .unwrap_or_else(SystemTime::now)
+    }
+}
+
+impl<S, Sink, TSpanConstructor> Layer<S> for ActionTraceLayer<Sink, TSpanConstructor>
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+    Sink: TraceSink + 'static,
+    TSpanConstructor: SpanConstructor + 'static,
+{
+    fn on_layer(&mut self, _subscriber: &mut S) {
+        self.action_span_access = WithActionSpan::new(
+            access_action_span::<S>,
+            Self::set_remote_parent::<S>,
+            Self::now,
+        );
+    }
+
+    fn on_new_span(&self, attributes: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => {
+                log::debug!("new span is not in the registry - not recording it");
+                return;
+            }
+        };
+
+        // The registry resolves explicit and contextual parents for us.
+        let (parent_unsampled, parent_context) = match span.parent() {
+            Some(parent) => {
+                let extensions = parent.extensions();
+                (
+                    extensions.get::<Unsampled>().is_some(),
+                    extensions.get::<ActionSpan>().map(ActionSpan::span_context),
+                )
+            }
+            None => (false, None),
+        };
+        let remote_parent = propagation::remote_parent(attributes);
+        let parent_dropped = parent_unsampled
+            || parent_context
+                .as_ref()
+                .is_some_and(|parent_context| !parent_context.is_sampled());
+        if remote_parent.is_none() && parent_dropped {
+            log::trace!("parent span is not sampled - neither is its child");
+            span.extensions_mut().insert(Unsampled);
+            return;
+        }
+
+        let mut action_span = match (remote_parent, parent_context) {
+            (None, Some(parent_context)) => {
+                log::debug!("found parent span - starting new child");
+                let mut action_span = self.new_action_span();
+                action_span.start_child(
+                    attributes,
+                    &parent_context,
+                    self.id_generator.new_span_id(),
+                    self.clock.now(),
+                );
+                action_span
+            }
+            (remote_parent, _) => {
+                let trace_id = match &remote_parent {
+                    Some(remote_parent) => {
+                        log::debug!("found otel.parent - starting new local root");
+                        remote_parent.trace_id
+                    }
+                    None => {
+                        log::debug!("no parent span - starting new root");
+                        self.id_generator.new_trace_id()
+                    }
+                };
+                let decision = self.sampler.should_sample(&SamplingParameters {
+                    metadata: attributes.metadata(),
+                    attributes,
+                    trace_id: &trace_id,
+                    parent: remote_parent.as_ref(),
+                });
+                if decision == SamplingDecision::Drop {
+                    log::trace!("new trace is not sampled");
+                    span.extensions_mut().insert(Unsampled);
+                    return;
+                }
+                let mut action_span = self.new_action_span();
+                action_span.start_root(
+                    attributes,
+                    trace_id,
+                    self.id_generator.new_span_id(),
+                    self.clock.now(),
+                );
+                if let Some(remote_parent) = &remote_parent {
+                    action_span.set_remote_parent(remote_parent);
+                }
+                action_span
+            }
+        };
+        self.source_attributes.add_to_span(&mut action_span);
+        action_span.ref_count = 1;
+
+        span.extensions_mut().insert(action_span);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(action_span) = span.extensions_mut().get_mut::<ActionSpan>() {
+                action_span.record(values, self.clock.now());
+            }
+        }
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        let link = ctx.span(follows).and_then(|follows| {
+            follows
+                .extensions()
+                .get::<ActionSpan>()
+                .map(ActionLink::to_span)
+        });
+        match (ctx.span(id), link) {
+            (Some(span), Some(link)) => {
+                if let Some(action_span) = span.extensions_mut().get_mut::<ActionSpan>() {
+                    action_span.add_link(link);
+                }
+            }
+            _ => log::debug!("could not find followed span - not linking"),
+        }
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.event_span(event) {
+            let mut extensions = span.extensions_mut();
+            if let Some(action_span) = extensions.get_mut::<ActionSpan>() {
+                if action_span.drop_event_if_full() {
+                    return;
+                }
+                let mut action_event =
+                    ActionEvent::new(event, &action_span.limits, self.clock.now());
+                self.source_attributes
+                    .add_to_event(&mut action_event, &action_span.limits);
+                action_span.events.push(action_event);
+                return;
+            }
+            if extensions.get_mut::<Unsampled>().is_some() {
+                return;
+            }
+        }
+        if let Some(orphan_event_sink) = &self.orphan_event_sink {
+            let mut action_event = ActionEvent::new(event, &self.span_limits, self.clock.now());
+            self.source_attributes
+                .add_to_event(&mut action_event, &self.span_limits);
+            orphan_event_sink.sink_event(&mut action_event);
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let cpu_at_enter = self
+                .measure_cpu_time
+                .then(cpu_time::thread_cpu_time)
+                .flatten();
+            span.extensions_mut()
+                .replace(EnteredAt(self.clock.now(), cpu_at_enter));
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if let Some(EnteredAt(entered_at, cpu_at_enter)) = extensions.remove::<EnteredAt>() {
+                if let Some(action_span) = extensions.get_mut::<ActionSpan>() {
+                    action_span.exited(entered_at, self.clock.now(), self.slow_poll_threshold);
+                    if let Some(cpu_at_enter) = cpu_at_enter {
+                        if let Some(cpu_at_exit) = cpu_time::thread_cpu_time() {
+                            action_span.add_cpu_time(cpu_at_exit.saturating_sub(cpu_at_enter));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let closed_span = ctx
+            .span(&id)
+            .and_then(|span| span.extensions_mut().remove::<ActionSpan>());
+        if let Some(mut closed_span) = closed_span {
+            // The registry owns reference counting; this span is gone.
+            closed_span.ref_count = 0;
+            closed_span.end_at(self.clock.now());
+            log::trace!("Closed action span: {closed_span:?}");
+            if closed_span.is_sampled() {
+                self.span_sink.sink_trace(&mut closed_span);
+            }
+            closed_span.reset();
+            self.span_constructor.return_span(closed_span);
+        }
+    }
+
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const Self as *const ())
+        } else if id == TypeId::of::<WithActionSpan>() {
+            Some(&self.action_span_access as *const WithActionSpan as *const ())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::Instrument;
+    use tracing_subscriber::prelude::*;
+
+    use crate::{
+        sampler::{AlwaysOff, ParentBased},
+        span_constructor::LazySpanCache,
+        test_support::{SampleBobsRemoteParents, Spans, TestEventSink, TestSink},
+        ActionEvent, ActionLink, ActionSpan, ActionSpanExt, ActionTraceLayer, SpanContext,
+    };
+
+    #[tokio::test]
+    async fn layered_spans() {
+        let spans: Spans = Default::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_subscriber::filter::LevelFilter::DEBUG)
+            .with(ActionTraceLayer::new(
+                TestSink {
+                    spans: spans.clone(),
+                },
+                LazySpanCache::default(),
+            ));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        async {
+            tracing::info!("an event");
+            let subspan = tracing::info_span!("a subspan");
+            subspan.add_link(ActionLink::new([7; 16], [7; 8]));
+            async {}.instrument(subspan).await;
+        }
+        .instrument(tracing::info_span!("a root"))
+        .await;
+        tracing::trace_span!("filtered out").in_scope(|| {});
+
+        let spans: Vec<ActionSpan> = spans.lock().expect("local mutex").clone();
+        assert_eq!(2, spans.len());
+
+        let root_span = spans
+            .iter()
+            .find(|s| s.metadata.expect("there is metadata").name() == "a root")
+            .expect("there is a root span");
+        assert_eq!(None, root_span.parent_span_id);
+        assert_eq!(1, root_span.events.len());
+        assert!(0 < root_span.enter_count);
+
+        let subspan = spans
+            .iter()
+            .find(|s| s.metadata.expect("there is metadata").name() == "a subspan")
+            .expect("there is a subspan");
+        assert_eq!(root_span.trace_id, subspan.trace_id);
+        assert_eq!(Some(root_span.span_id), subspan.parent_span_id);
+        assert_eq!([7; 16], subspan.links[0].trace_id);
+    }
+
+    #[test]
+    fn sampled_spans() {
+        let spans: Spans = Default::default();
+        let subscriber = tracing_subscriber::registry().with(
+            ActionTraceLayer::new(
+                TestSink {
+                    spans: spans.clone(),
+                },
+                LazySpanCache::default(),
+            )
+            .with_sampler(ParentBased::new(AlwaysOff)),
+        );
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+        tracing::subscriber::with_default(subscriber, || {
+            let dropped = tracing::info_span!("dropped");
+            assert_eq!(None, dropped.span_context());
+            dropped.in_scope(|| {
+                tracing::info_span!("dropped child").in_scope(|| {});
+                tracing::info_span!("remote child", otel.parent = traceparent).in_scope(|| {});
+            });
+        });
+
+        let spans = spans.lock().expect("local mutex");
+        let names: Vec<&str> = spans.iter().map(|span| span.name()).collect();
+        assert_eq!(
+            vec!["remote child"],
+            names,
+            "a sampled remote parent overrides the dropped local parent"
+        );
+    }
+
+    #[test]
+    fn set_parent_samples_again() {
+        let spans: Spans = Default::default();
+        let subscriber = tracing_subscriber::registry().with(
+            ActionTraceLayer::new(
+                TestSink {
+                    spans: spans.clone(),
+                },
+                LazySpanCache::default(),
+            )
+            .with_sampler(SampleBobsRemoteParents),
+        );
+        let remote_parent = SpanContext {
+            trace_id: [3; 16],
+            span_id: [4; 8],
+            trace_flags: SpanContext::SAMPLED,
+            trace_state: Default::default(),
+            is_remote: true,
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            let bob = tracing::info_span!("request", user = "bob");
+            bob.set_parent(remote_parent.clone());
+            bob.in_scope(|| tracing::info_span!("bob child").in_scope(|| {}));
+            let alice = tracing::info_span!("request", user = "alice");
+            alice.set_parent(remote_parent.clone());
+            alice.in_scope(|| tracing::info_span!("alice child").in_scope(|| {}));
+        });
+
+        let spans = spans.lock().expect("local mutex");
+        let names: Vec<&str> = spans.iter().map(|span| span.name()).collect();
+        assert_eq!(vec!["bob child", "request"], names);
+        assert!(spans.iter().all(|span| span.trace_id == [3; 16]));
+    }
+
+    #[test]
+    fn orphan_events() {
+        let spans: Spans = Default::default();
+        let orphans: Arc<Mutex<Vec<ActionEvent>>> = Default::default();
+        let subscriber = tracing_subscriber::registry().with(
+            ActionTraceLayer::new(
+                TestSink {
+                    spans: spans.clone(),
+                },
+                LazySpanCache::default(),
+            )
+            .with_sampler(ParentBased::new(AlwaysOff))
+            .with_orphan_event_sink(TestEventSink {
+                events: orphans.clone(),
+            }),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("no span");
+            tracing::info_span!("dropped").in_scope(|| tracing::info!("unsampled span"));
+        });
+
+        let orphans = orphans.lock().expect("local mutex");
+        assert_eq!(
+            1,
+            orphans.len(),
+            "events in unsampled spans are not orphans"
+        );
+        assert_eq!(
+            "String(\"no span\")",
+            format!("{:?}", orphans[0].attributes["message"])
+        );
+    }
+}
@@ -1,60 +1,475 @@
 use std::{
-    collections::HashMap,
-    sync::{atomic::AtomicU64, Mutex},
+    any::TypeId,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
 };
 
+use arc_swap::{ArcSwap, Guard};
 use thread_local::ThreadLocal;
-use tracing::{metadata::LevelFilter, span, Level, Subscriber};
+use tracing::{metadata::LevelFilter, span, subscriber::Interest, Dispatch, Subscriber};
 
 use crate::{
     action_span::{ActionEvent, Resettable},
+    allocations::{self, Allocations},
+    clock::{Clock, SystemClock},
+    cpu_time,
+    id_generator::{IdGenerator, ThreadLocalRandom},
+    propagation,
+    sampler::{self, AlwaysOn, Sampler, SamplingDecision, SamplingParameters},
+    source_attributes::SourceAttributes,
     span_constructor::SpanConstructor,
-    ActionSpan,
+    span_ext::WithActionSpan,
+    span_store::SpanStore,
+    static_metadata::UNSAMPLED_SPAN,
+    stats::{SubscriberCounters, SubscriberStats},
+    trace_filter::FilterReloadHandle,
+    ActionLink, ActionSpan, SpanContext, SpanLimits, TraceFilter,
 };
 
 pub trait TraceSink {
     fn sink_trace(&self, trace: &mut ActionSpan);
+
+    /// Counters for `SubscriberStats::sink`, like how many exports succeeded. None by default.
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        Vec::new()
+    }
+}
+
+/// Receives events that happened outside of any span, like errors during startup or in
+/// background tasks that were not instrumented.
+pub trait EventSink {
+    fn sink_event(&self, event: &mut ActionEvent);
+}
+
+/// A span on this thread's stack, and what it needs to measure when it exits.
+#[derive(Debug)]
+struct EnteredSpan {
+    id: span::Id,
+    entered_at: SystemTime,
+    /// The thread's cpu time at enter, when cpu time is measured.
+    cpu_at_enter: Option<Duration>,
+    /// The thread's allocations when this span last became the innermost, when they are counted.
+    allocations_since: Option<Allocations>,
+}
+
+/// Clears the eviction flag when eviction is done, even if a sink panics.
+struct EvictingGuard<'a>(&'a AtomicBool);
+
+impl Drop for EvictingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
 }
 
 pub struct ActionTraceSubscriber<Sink, SpanConstructor> {
     id_counter: AtomicU64,
-    current_traces: Mutex<HashMap<span::Id, ActionSpan>>,
-    level: Option<Level>,
-    active_span_stack: ThreadLocal<Mutex<Vec<span::Id>>>,
+    current_traces: SpanStore,
+    filter: Arc<ArcSwap<TraceFilter>>,
+    active_span_stack: ThreadLocal<Mutex<Vec<EnteredSpan>>>,
     span_sink: Sink,
     span_constructor: SpanConstructor,
+    action_span_access: WithActionSpan,
+    orphan_event_sink: Option<Box<dyn EventSink + Send + Sync>>,
+    sampler: Box<dyn Sampler + Send + Sync>,
+    span_limits: SpanLimits,
+    id_generator: Box<dyn IdGenerator + Send + Sync>,
+    clock: Box<dyn Clock + Send + Sync>,
+    measure_cpu_time: bool,
+    capture_backtraces: bool,
+    count_allocations: bool,
+    slow_poll_threshold: Option<Duration>,
+    source_attributes: SourceAttributes,
+    max_live_spans: Option<usize>,
+    max_span_age: Option<Duration>,
+    /// Nanoseconds since the epoch when spans should next be checked for age.
+    next_age_check: AtomicU64,
+    /// Set while a thread is evicting, so other threads skip it instead of piling up behind it.
+    evicting: AtomicBool,
+    counters: SubscriberCounters,
 }
 
-impl<Sink: TraceSink, TSpanConstructor: SpanConstructor>
+impl<Sink: TraceSink + 'static, TSpanConstructor: SpanConstructor + 'static>
     ActionTraceSubscriber<Sink, TSpanConstructor>
 {
     pub fn new(level: LevelFilter, sink: Sink, span_constructor: TSpanConstructor) -> Self {
         Self {
             id_counter: Default::default(),
             current_traces: Default::default(),
-            level: level.into_level(),
+            filter: Arc::new(ArcSwap::from_pointee(level.into())),
             active_span_stack: ThreadLocal::new(),
             span_sink: sink,
             span_constructor,
+            action_span_access: WithActionSpan::new(
+                Self::access_action_span,
+                Self::set_remote_parent,
+                Self::now,
+            ),
+            orphan_event_sink: None,
+            sampler: Box::new(AlwaysOn),
+            span_limits: Default::default(),
+            id_generator: Box::new(ThreadLocalRandom),
+            clock: Box::new(SystemClock),
+            measure_cpu_time: false,
+            capture_backtraces: false,
+            count_allocations: false,
+            slow_poll_threshold: None,
+            source_attributes: Default::default(),
+            max_live_spans: None,
+            max_span_age: None,
+            next_age_check: AtomicU64::new(0),
+            evicting: AtomicBool::new(false),
+            counters: Default::default(),
+        }
+    }
+
+    /// Like `new()`, but also returns a handle for swapping the filter while the subscriber is live.
+    ///
+    /// The handle is cheap to clone, so you can hand it to an admin endpoint and turn on debug
+    /// tracing during an incident without restarting.
+    pub fn new_reloadable(
+        filter: TraceFilter,
+        sink: Sink,
+        span_constructor: TSpanConstructor,
+    ) -> (Self, FilterReloadHandle) {
+        let subscriber = Self::new(LevelFilter::OFF, sink, span_constructor).with_filter(filter);
+        let handle = FilterReloadHandle::new(subscriber.filter.clone());
+        (subscriber, handle)
+    }
+
+    /// Record the spans enabled by `filter` instead of `new()`'s level, like a `TraceFilter`
+    /// parsed from directives such as `"my_crate::db=trace,hyper=warn,info"`.
+    pub fn with_filter(self, filter: TraceFilter) -> Self {
+        self.filter.store(Arc::new(filter));
+        self
+    }
+
+    /// Spread live spans over `shard_count` independently locked maps.
+    ///
+    /// The default is a few shards per core. 1 shard puts every span behind one lock.
+    pub fn with_span_store_shards(mut self, shard_count: usize) -> Self {
+        self.current_traces = SpanStore::new(shard_count);
+        self
+    }
+
+    /// Send events that have no span, or whose span was evicted, to `sink`. Without one, those
+    /// events are dropped and counted.
+    pub fn with_orphan_event_sink(mut self, sink: impl EventSink + Send + Sync + 'static) -> Self {
+        self.orphan_event_sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Decide which new traces to record with `sampler`. By default, every trace is recorded.
+    pub fn with_sampler(mut self, sampler: impl Sampler + Send + Sync + 'static) -> Self {
+        self.sampler = Box::new(sampler);
+        self
+    }
+
+    /// Cap the attributes, events and links each span can hold. What goes over is dropped and counted.
+    pub fn with_span_limits(mut self, span_limits: SpanLimits) -> Self {
+        self.span_limits = span_limits;
+        self
+    }
+
+    /// Make trace and span ids with `id_generator`, like `id_generator::XRay` when you send traces
+    /// to AWS X-Ray. By default ids are random.
+    pub fn with_id_generator(
+        mut self,
+        id_generator: impl IdGenerator + Send + Sync + 'static,
+    ) -> Self {
+        self.id_generator = Box::new(id_generator);
+        self
+    }
+
+    /// Take span and event timestamps from `clock`. By default this is the `SystemClock`. Use a
+    /// `MonotonicClock` if your system clock gets stepped.
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Measure the CPU time spent inside each span, into `ActionSpan::cpu_time`.
+    ///
+    /// This reads the thread's CPU clock on every enter and exit, so it is off by default.
+    /// Spans always exit on the thread they entered on, so this is accurate for async tasks
+    /// that move between threads. Only Linux is supported; elsewhere this does nothing.
+    pub fn with_cpu_time(mut self, measure_cpu_time: bool) -> Self {
+        self.measure_cpu_time = measure_cpu_time;
+        self
+    }
+
+    /// Add a backtrace of where each error was recorded to its `exception.stacktrace`.
+    ///
+    /// Capturing a backtrace is slow, so this is off by default, and the stacktrace only lists
+    /// the error's `source()` chain.
+    pub fn with_backtraces(mut self, capture_backtraces: bool) -> Self {
+        self.capture_backtraces = capture_backtraces;
+        self
+    }
+
+    /// Count heap allocations into `ActionSpan::allocations` and `ActionSpan::allocated_bytes`.
+    ///
+    /// Allocations are charged to the innermost entered span only, so a parent does not include
+    /// its children's. Nothing is counted unless `allocations::CountingAllocator` is your
+    /// global allocator.
+    pub fn with_allocation_counts(mut self, count_allocations: bool) -> Self {
+        self.count_allocations = count_allocations;
+        self
+    }
+
+    /// Flag spans that stay entered longer than `threshold` at once, like 10ms.
+    ///
+    /// For an instrumented future each enter is a poll, so this finds tasks that block the
+    /// executor. Slow spans get `slow_poll` set and a `slow poll` warning event for each slow poll.
+    pub fn with_slow_poll_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_poll_threshold = Some(threshold);
+        self
+    }
+
+    /// Add `code.filepath`, `code.lineno`, `code.namespace` and `target` attributes to spans and
+    /// events, from their `tracing` metadata. Off by default.
+    pub fn with_code_attributes(mut self, enabled: bool) -> Self {
+        self.source_attributes.code = enabled;
+        self
+    }
+
+    /// Add `thread.id` and `thread.name` attributes to spans and events, for the thread that
+    /// started or recorded them. Thread ids are numbered by tracing-actions. Off by default.
+    pub fn with_thread_attributes(mut self, enabled: bool) -> Self {
+        self.source_attributes.thread = enabled;
+        self
+    }
+
+    /// Keep at most `max_live_spans` spans open, so leaked spans can't grow memory without bound.
+    ///
+    /// When a new span goes over, the oldest open spans are force-closed until there are a tenth
+    /// fewer than the max. They are marked `truncated` and sent to the sink.
+    pub fn with_max_live_spans(mut self, max_live_spans: usize) -> Self {
+        self.max_live_spans = Some(max_live_spans);
+        self
+    }
+
+    /// Force-close spans that have been open longer than `max_span_age`, like a span that was
+    /// `mem::forget`'d. They are marked `truncated` and sent to the sink.
+    ///
+    /// Ages are checked as new spans start, at most twice per `max_span_age`, so a leaked span
+    /// can live up to half again as long before it is closed.
+    pub fn with_max_span_age(mut self, max_span_age: Duration) -> Self {
+        self.max_span_age = Some(max_span_age);
+        self
+    }
+
+    /// Counters for what the subscriber, its span constructor and its sink have been doing.
+    pub fn stats(&self) -> SubscriberStats {
+        SubscriberStats {
+            live_spans: self.current_traces.live_count() as u64,
+            span_cache: self.span_constructor.stats(),
+            sink: self.span_sink.stats(),
+            ..self.counters.stats()
+        }
+    }
+
+    /// Copies of up to `count` open spans, oldest first. For finding where leaked spans come from.
+    pub fn oldest_open_spans(&self, count: usize) -> Vec<ActionSpan> {
+        self.current_traces
+            .oldest(count)
+            .iter()
+            .filter_map(|id| self.current_traces.use_span(id, |span| span.clone()))
+            .collect()
+    }
+
+    fn evict_leaked_spans(&self) {
+        if self.max_span_age.is_none() && self.max_live_spans.is_none() {
+            return;
+        }
+        let now = self.clock.now();
+        let now_nanos = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let age_check_due =
+            self.max_span_age.is_some() && self.next_age_check.load(Ordering::Relaxed) <= now_nanos;
+        let over_live_limit = self
+            .max_live_spans
+            .is_some_and(|max_live_spans| max_live_spans < self.current_traces.live_count());
+        if !(age_check_due || over_live_limit) {
+            return;
+        }
+        // Only one thread evicts at a time. The others go on starting their spans.
+        if self.evicting.swap(true, Ordering::Acquire) {
+            return;
+        }
+        let _evicting = EvictingGuard(&self.evicting);
+
+        if let Some(max_span_age) = self.max_span_age.filter(|_| age_check_due) {
+            let check_again = now_nanos.saturating_add((max_span_age / 2).as_nanos() as u64);
+            self.next_age_check.store(check_again, Ordering::Relaxed);
+            let too_old = self.current_traces.remove_where(|span| {
+                now.duration_since(span.start)
+                    .is_ok_and(|age| max_span_age < age)
+            });
+            for (id, span) in too_old {
+                self.force_close(id, span, now);
+            }
+        }
+        if let Some(max_live_spans) = self.max_live_spans {
+            let live_count = self.current_traces.live_count();
+            if max_live_spans < live_count {
+                let keep = max_live_spans - max_live_spans / 10;
+                for id in self.current_traces.oldest(live_count - keep) {
+                    if let Some(span) = self.current_traces.remove(&id) {
+                        self.force_close(id, span, now);
+                    }
+                }
+            }
         }
     }
 
+    fn force_close(&self, id: span::Id, mut span: ActionSpan, now: SystemTime) {
+        log::warn!(
+            "force-closing span {} that started at {:?} - was it leaked?",
+            span.name(),
+            span.start
+        );
+        self.counters.count(|counts| &counts.spans_evicted);
+        // The filter scope may be entered on another thread. That thread ends it when it exits,
+        // and the last handle to close removes it.
+        if self.filter().has_scope(&id) {
+            self.current_traces.detach(id, span.ref_count);
+        }
+        span.truncated = true;
+        span.end_at(now);
+        if span.is_sampled() {
+            self.span_sink.sink_trace(&mut span);
+        }
+        span.reset();
+        self.span_constructor.return_span(span);
+    }
+
+    fn close_detached(&self, id: &span::Id) -> bool {
+        let closed = self.current_traces.close_detached(id);
+        if closed {
+            self.filter().on_close(id);
+        }
+        closed
+    }
+
+    fn filter(&self) -> Guard<Arc<TraceFilter>> {
+        self.filter.load()
+    }
+
+    fn current_span_id(&self) -> Option<span::Id> {
+        self.active_span_stack
+            .get_or_default()
+            .lock()
+            .expect("current trace mutex should not be poisoned")
+            .last()
+            .map(|entered| entered.id.clone())
+    }
+
     fn insert_new_span(&self, id: span::Id, mut action_span: ActionSpan) {
         action_span.ref_count = 1; // New spans are always inserted with 1
-        let mut traces = self
-            .current_traces
-            .lock()
-            .expect("trace mutex should not be poisoned");
-        traces.insert(id, action_span);
+        self.current_traces.insert(id, action_span);
     }
 
     fn use_span<T>(&self, id: &span::Id, use_it: impl FnOnce(&mut ActionSpan) -> T) -> Option<T> {
-        let mut traces = self
-            .current_traces
-            .lock()
-            .expect("trace mutex should not be poisoned");
+        if is_unsampled(id) {
+            return None;
+        }
+        self.current_traces.use_span(id, use_it)
+    }
+
+    fn access_action_span(
+        dispatch: &Dispatch,
+        id: &span::Id,
+        use_it: &mut dyn FnMut(&mut ActionSpan),
+    ) {
+        if let Some(subscriber) = dispatch.downcast_ref::<Self>() {
+            subscriber.use_span(id, use_it);
+        }
+    }
+
+    fn now(dispatch: &Dispatch) -> SystemTime {
+        dispatch
+            .downcast_ref::<Self>()
+            .map(|subscriber| subscriber.clock.now())
+            .unwrap_or_else(SystemTime::now)
+    }
 
-        traces.get_mut(id).map(use_it)
+    fn set_remote_parent(dispatch: &Dispatch, id: &span::Id, parent: &SpanContext) {
+        let subscriber = match dispatch.downcast_ref::<Self>() {
+            Some(subscriber) => subscriber,
+            None => return,
+        };
+        let reparented = subscriber.use_span(id, |span| {
+            sampler::set_remote_parent(subscriber.sampler.as_ref(), span, parent)
+        });
+        if reparented.is_none() {
+            // Unsampled spans are not stored, so there is nothing to give a parent.
+            log::debug!("span is not recorded - can not set its parent. Try the otel.parent field");
+        }
+    }
+
+    fn span_started(&self, attributes: &span::Attributes<'_>, id: &span::Id) {
+        let opened_scope = self.filter().on_new_span(attributes, id);
+        if opened_scope && is_unsampled(id) {
+            // Stored spans count their handles. Unsampled ones only need to for their filter scope.
+            self.current_traces.detach(id.clone(), 1);
+        }
+        self.evict_leaked_spans();
+    }
+
+    /// Events without a recorded span go to the orphan event sink, or are dropped without one.
+    fn orphan_event(&self, event: &tracing::Event<'_>, now: SystemTime) {
+        if let Some(orphan_event_sink) = &self.orphan_event_sink {
+            let mut action_event = ActionEvent::new(event, &self.span_limits, now);
+            self.source_attributes
+                .add_to_event(&mut action_event, &self.span_limits);
+            orphan_event_sink.sink_event(&mut action_event);
+        } else {
+            self.counters.count(|counts| &counts.events_dropped);
+        }
+    }
+
+    /// Start a trace, or continue a remote one, if the sampler wants it.
+    fn new_root(
+        &self,
+        id: span::Id,
+        attributes: &span::Attributes<'_>,
+        remote_parent: Option<&SpanContext>,
+    ) -> span::Id {
+        let trace_id = match remote_parent {
+            Some(remote_parent) => remote_parent.trace_id,
+            None => self.id_generator.new_trace_id(),
+        };
+        let decision = self.sampler.should_sample(&SamplingParameters {
+            metadata: attributes.metadata(),
+            attributes,
+            trace_id: &trace_id,
+            parent: remote_parent,
+        });
+        if decision == SamplingDecision::Drop {
+            log::trace!("new trace is not sampled");
+            self.counters.count(|counts| &counts.spans_sampled_out);
+            return unsampled(id);
+        }
+        let mut action_span = self.span_constructor.new_span();
+        action_span.limits = self.span_limits;
+        action_span.capture_backtraces = self.capture_backtraces;
+        action_span.start_root(
+            attributes,
+            trace_id,
+            self.id_generator.new_span_id(),
+            self.clock.now(),
+        );
+        if let Some(remote_parent) = remote_parent {
+            action_span.set_remote_parent(remote_parent);
+        }
+        self.source_attributes.add_to_span(&mut action_span);
+        self.insert_new_span(id.clone(), action_span);
+        id
     }
 
     fn possibly_remove_span(
@@ -62,32 +477,34 @@ impl<Sink: TraceSink, TSpanConstructor: SpanConstructor>
         id: &span::Id,
         use_it: impl FnOnce(&mut ActionSpan) -> bool,
     ) -> Option<ActionSpan> {
-        let mut traces = self
-            .current_traces
-            .lock()
-            .expect("trace mutex should not be poisoned");
-
-        match traces.get_mut(id).map(use_it) {
-            Some(remove_it) => {
-                if remove_it {
-                    traces.remove(id)
-                } else {
-                    None
-                }
-            }
-            None => None,
-        }
+        self.current_traces.possibly_remove(id, use_it)
     }
 }
 
+/// Unsampled spans are not stored anywhere. Their ids are marked so we know what they are.
+const UNSAMPLED_ID_BIT: u64 = 1 << 63;
+
+fn unsampled(id: span::Id) -> span::Id {
+    span::Id::from_u64(id.into_u64() | UNSAMPLED_ID_BIT)
+}
+
+fn is_unsampled(id: &span::Id) -> bool {
+    id.into_u64() & UNSAMPLED_ID_BIT != 0
+}
+
 impl<Sink: TraceSink + 'static, TSpanConstructor: SpanConstructor + 'static> Subscriber
     for ActionTraceSubscriber<Sink, TSpanConstructor>
 {
+    fn register_callsite(&self, metadata: &'static tracing::Metadata<'static>) -> Interest {
+        self.filter().register_callsite(metadata)
+    }
+
     fn enabled(&self, metadata: &tracing::Metadata<'_>) -> bool {
-        match &self.level {
-            Some(level) => metadata.level() <= level,
-            None => false,
-        }
+        self.filter().enabled(metadata)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.filter().max_level())
     }
 
     fn new_span(&self, attributes: &span::Attributes<'_>) -> span::Id {
@@ -103,73 +520,116 @@ impl<Sink: TraceSink + 'static, TSpanConstructor: SpanConstructor + 'static> Sub
         }
         log::debug!("new span: {id} - {attributes:?}");
 
+        self.counters.count(|counts| &counts.spans_created);
         let id = span::Id::from_u64(id);
-        let mut action_span = self.span_constructor.new_span();
-        match attributes.parent() {
-            Some(parent) => {
-                let parent_result = self.use_span(parent, |parent| {
-                    log::debug!("found parent span - starting new child");
-                    action_span.start_child(attributes, &parent.trace_id, &parent.span_id)
-                });
-                if parent_result.is_none() {
-                    log::debug!("could not find parent span - starting new root");
-                    action_span.start_root(attributes);
-                }
+        let parent = if attributes.is_contextual() {
+            self.current_span_id()
+        } else {
+            attributes.parent().cloned()
+        };
+        let parent_context = parent
+            .as_ref()
+            .and_then(|parent| self.use_span(parent, |parent| parent.span_context()));
+
+        if let Some(remote_parent) = propagation::remote_parent(attributes) {
+            log::debug!("found otel.parent - starting new local root");
+            let id = self.new_root(id, attributes, Some(&remote_parent));
+            self.span_started(attributes, &id);
+            return id;
+        }
+        let id = match (&parent, parent_context) {
+            (Some(parent), _) if is_unsampled(parent) => {
+                log::trace!("parent span is not sampled - neither is its child");
+                self.counters.count(|counts| &counts.spans_sampled_out);
+                unsampled(id)
             }
-            None => {
-                if attributes.is_contextual() {
-                    let current = self.current_span();
-                    match current.id() {
-                        Some(current_id) => {
-                            match self.use_span(current_id, |current| {
-                                action_span.start_child(
-                                    attributes,
-                                    &current.trace_id,
-                                    &current.span_id,
-                                );
-                            }) {
-                                Some(_) => (),
-                                None => {
-                                    log::debug!("could not find indicated current active span - starting new root");
-                                    action_span.start_root(attributes)
-                                }
-                            }
-                        }
-                        None => {
-                            log::debug!("no current span - starting new root");
-                            action_span.start_root(attributes)
-                        }
-                    }
-                } else {
-                    log::debug!("no parent span - starting new root");
-                    action_span.start_root(attributes)
+            (_, Some(parent_context)) if !parent_context.is_sampled() => {
+                log::trace!("parent span is not sampled - neither is its child");
+                self.counters.count(|counts| &counts.spans_sampled_out);
+                unsampled(id)
+            }
+            (_, Some(parent_context)) => {
+                log::debug!("found parent span - starting new child");
+                let mut action_span = self.span_constructor.new_span();
+                action_span.limits = self.span_limits;
+                action_span.capture_backtraces = self.capture_backtraces;
+                action_span.start_child(
+                    attributes,
+                    &parent_context,
+                    self.id_generator.new_span_id(),
+                    self.clock.now(),
+                );
+                self.source_attributes.add_to_span(&mut action_span);
+                self.insert_new_span(id.clone(), action_span);
+                id
+            }
+            (_, None) => {
+                match parent {
+                    Some(_) => log::debug!("could not find parent span - starting new root"),
+                    None => log::debug!("no parent span - starting new root"),
                 }
+                self.new_root(id, attributes, None)
             }
-        }
+        };
 
-        self.insert_new_span(id.clone(), action_span);
+        self.span_started(attributes, &id);
         id
     }
 
     fn record(&self, span: &span::Id, values: &span::Record<'_>) {
-        self.use_span(span, |span| values.record(span));
+        let now = self.clock.now();
+        self.use_span(span, |span| span.record(values, now));
     }
 
-    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+    fn record_follows_from(&self, span: &span::Id, follows: &span::Id) {
+        // Look the spans up one at a time: they may share a shard.
+        match self.use_span(follows, |follows| ActionLink::to_span(follows)) {
+            Some(link) => {
+                self.use_span(span, |span| span.add_link(link));
+            }
+            None => log::debug!("could not find followed span - not linking"),
+        }
+    }
 
     fn event(&self, event: &tracing::Event<'_>) {
-        let mut active_span = self
-            .active_span_stack
-            .get_or_default()
-            .lock()
-            .expect("threadlocal current")
-            .last()
-            .cloned();
-        active_span
-            .map(|id| self.use_span(&id, |span| span.events.push(ActionEvent::from(event))));
+        let parent = if event.is_contextual() {
+            self.current_span_id()
+        } else {
+            event.parent().cloned()
+        };
+        let now = self.clock.now();
+        match parent {
+            // Events in unsampled spans are sampled out with them.
+            Some(id) if is_unsampled(&id) => (),
+            Some(id) => {
+                let recorded = self.use_span(&id, |span| {
+                    if !span.is_sampled() {
+                        return;
+                    }
+                    if span.drop_event_if_full() {
+                        self.counters.count(|counts| &counts.events_dropped);
+                        return;
+                    }
+                    let mut action_event = ActionEvent::new(event, &span.limits, now);
+                    self.source_attributes
+                        .add_to_event(&mut action_event, &span.limits);
+                    span.events.push(action_event);
+                });
+                if recorded.is_none() {
+                    log::debug!("event parent is not recorded, like an evicted span - sending it as an orphan");
+                    self.orphan_event(event, now);
+                }
+            }
+            None => self.orphan_event(event, now),
+        }
     }
 
     fn enter(&self, span: &span::Id) {
+        let entered_at = self.clock.now();
+        let cpu_at_enter = self
+            .measure_cpu_time
+            .then(cpu_time::thread_cpu_time)
+            .flatten();
         let mut active_trace = self
             .active_span_stack
             .get_or_default()
@@ -180,10 +640,33 @@ impl<Sink: TraceSink + 'static, TSpanConstructor: SpanConstructor + 'static> Sub
             *active_trace,
             span
         );
-        active_trace.push(span.clone());
+        let mut allocations_since = None;
+        let mut parent_allocations = None;
+        if self.count_allocations {
+            let now = allocations::thread_allocations();
+            allocations_since = Some(now);
+            // The span being entered takes over from its parent, so the parent is charged up to now.
+            if let Some(parent) = active_trace.last_mut() {
+                if let Some(parent_since) = parent.allocations_since.replace(now) {
+                    parent_allocations = Some((parent.id.clone(), now - parent_since));
+                }
+            }
+        }
+        active_trace.push(EnteredSpan {
+            id: span.clone(),
+            entered_at,
+            cpu_at_enter,
+            allocations_since,
+        });
+        drop(active_trace);
+        if let Some((parent, allocations)) = parent_allocations {
+            self.use_span(&parent, |parent| parent.add_allocations(allocations));
+        }
+        self.filter().on_enter(span);
     }
 
     fn exit(&self, span: &span::Id) {
+        let allocations_at_exit = self.count_allocations.then(allocations::thread_allocations);
         let mut active_span_stack = self
             .active_span_stack
             .get_or_default()
@@ -194,8 +677,32 @@ impl<Sink: TraceSink + 'static, TSpanConstructor: SpanConstructor + 'static> Sub
             active_span_stack.last(),
             span
         );
-        if active_span_stack.last() == Some(span) {
-            active_span_stack.pop();
+        if active_span_stack.last().map(|entered| &entered.id) == Some(span) {
+            let entered = active_span_stack.pop().expect("the span was just there");
+            if let Some(now) = allocations_at_exit {
+                // The parent is the innermost span again.
+                if let Some(parent) = active_span_stack.last_mut() {
+                    if parent.allocations_since.is_some() {
+                        parent.allocations_since = Some(now);
+                    }
+                }
+            }
+            drop(active_span_stack);
+            self.filter().on_exit(span);
+            let now = self.clock.now();
+            let cpu_time = entered.cpu_at_enter.and_then(|cpu_at_enter| {
+                cpu_time::thread_cpu_time()
+                    .map(|cpu_at_exit| cpu_at_exit.saturating_sub(cpu_at_enter))
+            });
+            self.use_span(span, |span| {
+                span.exited(entered.entered_at, now, self.slow_poll_threshold);
+                if let Some(cpu_time) = cpu_time {
+                    span.add_cpu_time(cpu_time);
+                }
+                if let (Some(since), Some(now)) = (entered.allocations_since, allocations_at_exit) {
+                    span.add_allocations(now - since);
+                }
+            });
         } else {
             log::trace!(
                 "tried to exit non-active span. Current: {:?}, attempted: {:?}",
@@ -206,15 +713,10 @@ impl<Sink: TraceSink + 'static, TSpanConstructor: SpanConstructor + 'static> Sub
     }
 
     fn current_span(&self) -> tracing_core::span::Current {
-        let current = self
-            .active_span_stack
-            .get_or_default()
-            .lock()
-            .expect("current trace mutex should not be poisoned")
-            .last()
-            .cloned();
-
-        match current {
+        match self.current_span_id() {
+            Some(span) if is_unsampled(&span) => {
+                tracing_core::span::Current::new(span, &UNSAMPLED_SPAN)
+            }
             Some(span) => match self.use_span(&span, |s| s.metadata).unwrap_or_default() {
                 Some(metadata) => tracing_core::span::Current::new(span, metadata),
                 None => tracing_core::span::Current::none(),
@@ -224,64 +726,80 @@ impl<Sink: TraceSink + 'static, TSpanConstructor: SpanConstructor + 'static> Sub
     }
 
     fn clone_span(&self, id: &span::Id) -> span::Id {
-        self.use_span(id, |span| span.ref_count += 1);
+        if self.use_span(id, |span| span.ref_count += 1).is_none() {
+            self.current_traces.clone_detached(id);
+        }
         id.clone()
     }
 
     fn try_close(&self, id: span::Id) -> bool {
+        if is_unsampled(&id) {
+            return self.close_detached(&id);
+        }
+        let now = self.clock.now();
         let closed_span = self.possibly_remove_span(&id, |span| {
             span.ref_count -= 1;
             span.ref_count == 0
         });
         match closed_span {
             Some(mut closed_span) => {
-                closed_span.end();
+                self.filter().on_close(&id);
+                closed_span.end_at(now);
                 log::trace!("Closed action span: {closed_span:?}");
-                self.span_sink.sink_trace(&mut closed_span);
+                if closed_span.is_sampled() {
+                    self.counters.count(|counts| &counts.spans_closed);
+                    self.span_sink.sink_trace(&mut closed_span);
+                } else {
+                    // Dropped by the sampler after it started, with `set_parent()`.
+                    self.counters.count(|counts| &counts.spans_sampled_out);
+                }
                 closed_span.reset();
                 self.span_constructor.return_span(closed_span);
                 true
             }
-            None => false,
+            None => self.close_detached(&id),
+        }
+    }
+
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const Self as *const ())
+        } else if id == TypeId::of::<WithActionSpan>() {
+            Some(&self.action_span_access as *const WithActionSpan as *const ())
+        } else {
+            None
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::sync::{Arc, Mutex};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Barrier, Mutex},
+        time::{Duration, SystemTime},
+    };
 
-    use tracing::Instrument;
+    use tracing::{Dispatch, Instrument};
     use tracing_core::dispatcher::DefaultGuard;
 
-    use crate::{span_constructor::LazySpanCache, ActionSpan, ActionTraceSubscriber, TraceSink};
+    use crate::{
+        clock::MockClock,
+        id_generator::{IdGenerator, Seeded},
+        propagation,
+        sampler::{AlwaysOff, AlwaysOn, ParentBased},
+        span_constructor::LazySpanCache,
+        test_support::{
+            test_subscriber, test_subscriber_with, SampleBobsRemoteParents, Spans, TestEventSink,
+            TestSink,
+        },
+        ActionEvent, ActionLink, ActionSpan, ActionSpanExt, ActionTraceSubscriber, AttributeValue,
+        SpanCacheStats, SpanContext, SpanLimits, SpanStatus, TraceKind,
+    };
 
-    struct TestSink {
-        spans: Arc<Mutex<Vec<ActionSpan>>>,
-    }
-    impl TraceSink for TestSink {
-        fn sink_trace(&self, action_span: &mut ActionSpan) {
-            self.spans
-                .lock()
-                .expect("local lock should work")
-                .push(action_span.clone());
-        }
-    }
-
-    fn set_up_tracing() -> (DefaultGuard, Arc<Mutex<Vec<ActionSpan>>>) {
-        static INITIALIZE_LOGGER_ONCE: std::sync::Once = std::sync::Once::new();
-        INITIALIZE_LOGGER_ONCE.call_once(|| {
-            env_logger::builder().is_test(true).init();
-        });
-        let level = "debug".parse().expect("debug is a level filter");
-        let spans: Arc<Mutex<Vec<ActionSpan>>> = Default::default();
-        let k_logging_subscriber = ActionTraceSubscriber::new(
-            level,
-            TestSink {
-                spans: spans.clone(),
-            },
-            LazySpanCache::default(),
-        );
+    fn set_up_tracing() -> (DefaultGuard, Spans) {
+        let spans: Spans = Default::default();
+        let k_logging_subscriber = test_subscriber(&spans);
         (
             tracing::subscriber::set_default(k_logging_subscriber),
             spans,
@@ -315,6 +833,605 @@ mod test {
         }
     }
 
+    #[test]
+    fn span_kinds() {
+        let (_guard, spans) = set_up_tracing();
+
+        tracing::info_span!("root").in_scope(|| {
+            tracing::info_span!("internal").in_scope(|| {});
+            tracing::info_span!("client", otel.kind = "client").in_scope(|| {});
+            let producer = tracing::info_span!(
+                "producer",
+                otel.kind = tracing::field::Empty,
+                otel.name = "publish orders"
+            );
+            producer.record("otel.kind", "Producer");
+        });
+
+        let spans: Vec<ActionSpan> = spans.lock().expect("local mutex").clone();
+        let kinds: Vec<(&str, TraceKind)> =
+            spans.iter().map(|span| (span.name(), span.kind)).collect();
+        assert_eq!(
+            vec![
+                ("internal", TraceKind::Internal),
+                ("client", TraceKind::Client),
+                ("publish orders", TraceKind::Producer),
+                ("root", TraceKind::Server),
+            ],
+            kinds
+        );
+        assert!(spans.iter().all(|span| span.attributes.is_empty()));
+    }
+
+    #[test]
+    fn span_names() {
+        let (_guard, spans) = set_up_tracing();
+
+        let request = tracing::info_span!("request", otel.name = tracing::field::Empty);
+        request.record("otel.name", format_args!("GET /users/{}", "{id}"));
+        drop(request);
+
+        let spans = spans.lock().expect("local mutex");
+        assert_eq!("GET /users/{id}", spans[0].name());
+        assert_eq!(
+            "request",
+            spans[0].metadata.expect("there is metadata").name()
+        );
+        assert!(spans[0].attributes.is_empty());
+    }
+
+    #[test]
+    fn span_status() {
+        let (_guard, spans) = set_up_tracing();
+        let error = std::io::Error::other("disk full");
+
+        tracing::info_span!("unset").in_scope(|| {});
+        tracing::info_span!(
+            "failed",
+            error = &error as &(dyn std::error::Error + 'static)
+        )
+        .in_scope(|| {});
+        tracing::info_span!(
+            "explicit",
+            otel.status_code = "error",
+            otel.status_message = "503 Service Unavailable"
+        )
+        .in_scope(|| {});
+        let ok = tracing::info_span!("ok", otel.status_code = "ok", error = tracing::field::Empty);
+        ok.record("error", &error as &(dyn std::error::Error + 'static));
+        drop(ok);
+
+        let spans = spans.lock().expect("local mutex");
+        let statuses: Vec<(&str, SpanStatus, &str)> = spans
+            .iter()
+            .map(|span| (span.name(), span.status, span.status_message.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("unset", SpanStatus::Unset, ""),
+                ("failed", SpanStatus::Error, "disk full"),
+                ("explicit", SpanStatus::Error, "503 Service Unavailable"),
+                ("ok", SpanStatus::Ok, ""),
+            ],
+            statuses
+        );
+        assert!(spans[2].attributes.is_empty());
+    }
+
+    #[derive(Debug)]
+    struct QueryFailed(std::io::Error);
+    impl std::fmt::Display for QueryFailed {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("query failed")
+        }
+    }
+    impl std::error::Error for QueryFailed {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn exception_events() {
+        let (_guard, spans) = set_up_tracing();
+        let error = QueryFailed(std::io::Error::other("disk full"));
+        tracing::info_span!(
+            "query",
+            error = &error as &(dyn std::error::Error + 'static)
+        )
+        .in_scope(|| {});
+
+        let spans = spans.lock().expect("local mutex");
+        let exception = &spans[0].events[0];
+        assert_eq!("exception", exception.metadata.name());
+        assert_eq!(
+            None,
+            exception.attributes.get("exception.type"),
+            "a dyn Error does not know its type"
+        );
+        assert_eq!(
+            Some(&AttributeValue::String("query failed".to_string())),
+            exception.attributes.get("exception.message")
+        );
+        match exception.attributes.get("exception.stacktrace") {
+            Some(AttributeValue::String(stacktrace)) => {
+                assert_eq!("query failed\ncaused by: disk full", stacktrace)
+            }
+            other => panic!("unexpected stacktrace {other:?}"),
+        }
+    }
+
+    #[test]
+    fn exception_types() {
+        let (_guard, spans) = set_up_tracing();
+        let io_error = std::io::Error::other("disk full");
+        tracing::info_span!(
+            "read",
+            error = &io_error as &(dyn std::error::Error + 'static)
+        )
+        .in_scope(|| {});
+        let query = tracing::info_span!("query");
+        query.record_exception(&QueryFailed(std::io::Error::other("disk full")));
+        drop(query);
+
+        let spans = spans.lock().expect("local mutex");
+        assert_eq!(
+            Some(&AttributeValue::String("std::io::error::Error".to_string())),
+            spans[0].events[0].attributes.get("exception.type")
+        );
+        let exception = &spans[1].events[0];
+        assert_eq!(
+            Some(&AttributeValue::String(
+                "tracing_actions::action_trace_subscriber::test::QueryFailed".to_string()
+            )),
+            exception.attributes.get("exception.type")
+        );
+        assert_eq!(
+            Some(&AttributeValue::String("query failed".to_string())),
+            exception.attributes.get("exception.message")
+        );
+        assert_eq!(SpanStatus::Error, spans[1].status);
+        assert_eq!("query failed", spans[1].status_message);
+    }
+
+    #[test]
+    fn exception_backtraces() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber(&spans).with_backtraces(true);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let error = std::io::Error::other("disk full");
+            tracing::info_span!(
+                "query",
+                error = &error as &(dyn std::error::Error + 'static)
+            )
+            .in_scope(|| {});
+        });
+
+        let spans = spans.lock().expect("local mutex");
+        match spans[0].events[0].attributes.get("exception.stacktrace") {
+            Some(AttributeValue::String(stacktrace)) => {
+                assert!(stacktrace.starts_with("disk full\n\n"), "{stacktrace}")
+            }
+            other => panic!("unexpected stacktrace {other:?}"),
+        }
+    }
+
+    #[test]
+    fn span_limits() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber(&spans).with_span_limits(SpanLimits {
+            max_attributes_per_span: 2,
+            max_events_per_span: 2,
+            max_links_per_span: 1,
+            max_attributes_per_event: 1,
+            max_attributes_per_link: 1,
+            max_attribute_value_length: 4,
+        });
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "limited",
+                a = "truncated",
+                b = 2,
+                c = 3,
+                error = tracing::field::Empty
+            );
+            span.record("a", "é and more");
+            span.in_scope(|| {
+                for i in 0..5 {
+                    tracing::info!(i, j = i, "an event");
+                }
+            });
+            let error = std::io::Error::other("over the event limit");
+            span.record("error", &error as &(dyn std::error::Error + 'static));
+            span.add_link(
+                ActionLink::new([1; 16], [1; 8])
+                    .with_attribute("a", AttributeValue::I64(1))
+                    .with_attribute("b", AttributeValue::I64(2)),
+            );
+            span.add_link(ActionLink::new([2; 16], [2; 8]));
+        });
+
+        let spans = spans.lock().expect("local mutex");
+        let span = &spans[0];
+        assert_eq!(2, span.attributes.len());
+        assert_eq!(
+            Some(&AttributeValue::String("é an".to_string())),
+            span.attributes.get("a")
+        );
+        assert_eq!(2, span.dropped_attributes_count);
+        assert_eq!(2, span.events.len());
+        assert_eq!(4, span.dropped_events_count);
+        assert_eq!(SpanStatus::Error, span.status);
+        assert_eq!(1, span.events[0].attributes.len());
+        assert_eq!(2, span.events[0].dropped_attributes_count);
+        assert_eq!(1, span.links.len());
+        assert_eq!(1, span.dropped_links_count);
+        assert_eq!(1, span.links[0].attributes.len());
+        assert_eq!(1, span.links[0].dropped_attributes_count);
+    }
+
+    #[test]
+    fn seeded_ids() {
+        let record_ids = || {
+            let spans: Spans = Default::default();
+            let subscriber = test_subscriber(&spans).with_id_generator(Seeded::new(7));
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::info_span!("root")
+                    .in_scope(|| tracing::info_span!("child").in_scope(|| {}))
+            });
+            let ids: Vec<([u8; 16], [u8; 8])> = spans
+                .lock()
+                .expect("local mutex")
+                .iter()
+                .map(|span| (span.trace_id, span.span_id))
+                .collect();
+            ids
+        };
+
+        let ids = record_ids();
+        assert_eq!(ids, record_ids());
+        let expected = Seeded::new(7);
+        let (trace_id, root_span_id, child_span_id) = (
+            expected.new_trace_id(),
+            expected.new_span_id(),
+            expected.new_span_id(),
+        );
+        assert_eq!(
+            vec![(trace_id, child_span_id), (trace_id, root_span_id)],
+            ids
+        );
+    }
+
+    #[test]
+    fn mock_clock() {
+        let spans: Spans = Default::default();
+        let clock = MockClock::default();
+        let subscriber = test_subscriber(&spans).with_clock(clock.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("timed").in_scope(|| {
+                clock.advance(Duration::from_millis(5));
+                tracing::info!("halfway");
+                clock.advance(Duration::from_millis(5));
+            });
+        });
+
+        let spans = spans.lock().expect("local mutex");
+        let span = &spans[0];
+        assert_eq!(SystemTime::UNIX_EPOCH, span.start);
+        assert_eq!(SystemTime::UNIX_EPOCH + Duration::from_millis(10), span.end);
+        assert_eq!(
+            SystemTime::UNIX_EPOCH + Duration::from_millis(5),
+            span.events[0].timestamp
+        );
+    }
+
+    #[test]
+    fn busy_and_idle() {
+        let spans: Spans = Default::default();
+        let clock = MockClock::default();
+        let subscriber = test_subscriber(&spans).with_clock(clock.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let span = tracing::info_span!("polled");
+        for _ in 0..3 {
+            clock.advance(Duration::from_millis(10));
+            span.in_scope(|| clock.advance(Duration::from_millis(1)));
+        }
+        drop(span);
+
+        let spans = spans.lock().expect("local mutex");
+        assert_eq!(Duration::from_millis(3), spans[0].busy);
+        assert_eq!(Duration::from_millis(30), spans[0].idle);
+        assert_eq!(3, spans[0].enter_count);
+        assert_eq!(None, spans[0].cpu_time);
+    }
+
+    #[test]
+    fn source_attributes() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber(&spans)
+            .with_code_attributes(true)
+            .with_thread_attributes(true);
+
+        std::thread::Builder::new()
+            .name("worker".to_string())
+            .spawn(move || {
+                tracing::subscriber::with_default(subscriber, || {
+                    tracing::info_span!("located").in_scope(|| tracing::info!("an event"));
+                })
+            })
+            .expect("thread should spawn")
+            .join()
+            .expect("thread should finish");
+
+        let spans = spans.lock().expect("local mutex");
+        let span = &spans[0];
+        let event = &span.events[0];
+        for attributes in [&span.attributes, &event.attributes] {
+            assert_eq!(
+                Some(&AttributeValue::String(file!().to_string())),
+                attributes.get("code.filepath")
+            );
+            assert!(matches!(
+                attributes.get("code.lineno"),
+                Some(AttributeValue::I64(_))
+            ));
+            assert_eq!(
+                Some(&AttributeValue::String(module_path!().to_string())),
+                attributes.get("code.namespace")
+            );
+            assert_eq!(
+                Some(&AttributeValue::String(module_path!().to_string())),
+                attributes.get("target")
+            );
+            assert!(matches!(
+                attributes.get("thread.id"),
+                Some(AttributeValue::I64(_))
+            ));
+            assert_eq!(
+                Some(&AttributeValue::String("worker".to_string())),
+                attributes.get("thread.name")
+            );
+        }
+        assert_eq!(span.attributes["thread.id"], event.attributes["thread.id"]);
+    }
+
+    #[test]
+    fn old_spans_are_evicted() {
+        let spans: Spans = Default::default();
+        let clock = MockClock::default();
+        let subscriber = test_subscriber(&spans)
+            .with_clock(clock.clone())
+            .with_max_span_age(Duration::from_secs(10));
+        let dispatch = Dispatch::new(subscriber);
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            std::mem::forget(tracing::info_span!("leaked"));
+            clock.advance(Duration::from_secs(6));
+            let _young = tracing::info_span!("young");
+            assert!(spans.lock().expect("local mutex").is_empty());
+
+            clock.advance(Duration::from_secs(5));
+            tracing::info_span!("trigger").in_scope(|| {});
+        });
+
+        let subscriber = dispatch
+            .downcast_ref::<ActionTraceSubscriber<TestSink, LazySpanCache>>()
+            .expect("the dispatch holds the subscriber");
+        assert_eq!(1, subscriber.stats().spans_evicted);
+        let spans = spans.lock().expect("local mutex");
+        let leaked = spans
+            .iter()
+            .find(|span| span.name() == "leaked")
+            .expect("the leaked span was sunk");
+        assert!(leaked.truncated);
+        assert_eq!(SystemTime::UNIX_EPOCH + Duration::from_secs(11), leaked.end);
+        assert_eq!(
+            1,
+            spans.iter().filter(|span| span.truncated).count(),
+            "only the leaked span is too old"
+        );
+    }
+
+    #[test]
+    fn live_spans_are_limited() {
+        let spans: Spans = Default::default();
+        let clock = MockClock::default();
+        let subscriber = test_subscriber(&spans)
+            .with_clock(clock.clone())
+            .with_max_live_spans(10);
+        let dispatch = Dispatch::new(subscriber);
+        let subscriber = dispatch
+            .downcast_ref::<ActionTraceSubscriber<TestSink, LazySpanCache>>()
+            .expect("the dispatch holds the subscriber");
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            for i in 0..10 {
+                clock.advance(Duration::from_secs(1));
+                std::mem::forget(tracing::info_span!("leaked", i));
+            }
+            assert_eq!(0, subscriber.stats().spans_evicted);
+            let oldest: Vec<AttributeValue> = subscriber
+                .oldest_open_spans(2)
+                .iter()
+                .map(|span| span.attributes["i"].clone())
+                .collect();
+            assert_eq!(vec![AttributeValue::I64(0), AttributeValue::I64(1)], oldest);
+
+            clock.advance(Duration::from_secs(1));
+            std::mem::forget(tracing::info_span!("one too many"));
+        });
+
+        assert_eq!(2, subscriber.stats().spans_evicted);
+        let evicted: Vec<AttributeValue> = spans
+            .lock()
+            .expect("local mutex")
+            .iter()
+            .map(|span| {
+                assert!(span.truncated);
+                span.attributes["i"].clone()
+            })
+            .collect();
+        assert_eq!(
+            vec![AttributeValue::I64(0), AttributeValue::I64(1)],
+            evicted
+        );
+        assert_eq!(9, subscriber.oldest_open_spans(100).len());
+    }
+
+    #[test]
+    fn stats() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber_with(&spans, LazySpanCache::new(1))
+            .with_sampler(ParentBased::new(AlwaysOff))
+            .with_span_limits(SpanLimits {
+                max_events_per_span: 1,
+                ..Default::default()
+            });
+        let dispatch = Dispatch::new(subscriber);
+        let subscriber = dispatch
+            .downcast_ref::<ActionTraceSubscriber<TestSink, LazySpanCache>>()
+            .expect("the dispatch holds the subscriber");
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::info!("no span and no orphan sink");
+            let root = tracing::info_span!("sampled out");
+            root.in_scope(|| tracing::info_span!("child").in_scope(|| {}));
+            assert_eq!(0, subscriber.stats().live_spans);
+            drop(root);
+
+            let traceparent = "00-01010101010101010101010101010101-0202020202020202-01";
+            for _ in 0..2 {
+                let span = tracing::info_span!("continued", otel.parent = traceparent);
+                span.in_scope(|| {
+                    tracing::info!("kept");
+                    tracing::info!("over the limit");
+                });
+            }
+
+            let reconsidered = tracing::info_span!("reconsidered", otel.parent = traceparent);
+            reconsidered.set_parent(SpanContext {
+                trace_id: [3; 16],
+                span_id: [4; 8],
+                trace_flags: 0,
+                trace_state: Default::default(),
+                is_remote: true,
+            });
+            let stats = subscriber.stats();
+            assert_eq!(1, stats.live_spans);
+            assert_eq!(2, stats.spans_sampled_out, "counted when it closes");
+        });
+
+        let stats = subscriber.stats();
+        assert_eq!(5, stats.spans_created);
+        assert_eq!(2, stats.spans_closed);
+        assert_eq!(3, stats.spans_sampled_out);
+        assert_eq!(0, stats.spans_evicted);
+        assert_eq!(3, stats.events_dropped);
+        assert_eq!(0, stats.live_spans);
+        assert_eq!(
+            stats.spans_created,
+            stats.spans_closed + stats.spans_sampled_out + stats.spans_evicted + stats.live_spans
+        );
+        assert_eq!(
+            SpanCacheStats {
+                hits: 2,
+                misses: 1,
+                rejected_full: 0,
+                rejected_contended: 0,
+            },
+            stats.span_cache
+        );
+        assert!(stats.sink.is_empty());
+        assert_eq!(2, spans.lock().expect("local mutex").len());
+    }
+
+    #[test]
+    fn slow_polls() {
+        let spans: Spans = Default::default();
+        let clock = MockClock::default();
+        let subscriber = test_subscriber(&spans)
+            .with_clock(clock.clone())
+            .with_slow_poll_threshold(Duration::from_millis(10));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let span = tracing::info_span!("polled");
+        for poll in [1, 25, 3] {
+            span.in_scope(|| clock.advance(Duration::from_millis(poll)));
+        }
+        drop(span);
+        tracing::info_span!("quick").in_scope(|| clock.advance(Duration::from_millis(10)));
+
+        let spans = spans.lock().expect("local mutex");
+        let polled = &spans[0];
+        assert_eq!(3, polled.enter_count);
+        assert_eq!(Duration::from_millis(25), polled.longest_poll);
+        assert!(polled.slow_poll);
+        assert_eq!(1, polled.events.len());
+        let warning = &polled.events[0];
+        assert_eq!("slow poll", warning.metadata.name());
+        assert_eq!(tracing::Level::WARN, *warning.metadata.level());
+        assert_eq!(
+            Some(&AttributeValue::U64(25_000_000)),
+            warning.attributes.get("poll_ns")
+        );
+        assert_eq!(
+            SystemTime::UNIX_EPOCH + Duration::from_millis(26),
+            warning.timestamp
+        );
+
+        let quick = &spans[1];
+        assert_eq!(Duration::from_millis(10), quick.longest_poll);
+        assert!(!quick.slow_poll);
+        assert!(quick.events.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn cpu_time() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber(&spans).with_cpu_time(true);
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        tracing::info_span!("busy").in_scope(|| {
+            let start = std::time::Instant::now();
+            while start.elapsed() < Duration::from_millis(5) {
+                std::hint::spin_loop();
+            }
+        });
+
+        let spans = spans.lock().expect("local mutex");
+        let cpu_time = spans[0].cpu_time.expect("cpu time is measured");
+        assert!(Duration::ZERO < cpu_time);
+    }
+
+    #[test]
+    fn allocation_counts() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber(&spans).with_allocation_counts(true);
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        tracing::info_span!("parent").in_scope(|| {
+            let small: Vec<u8> = Vec::with_capacity(1000);
+            tracing::info_span!("child").in_scope(|| {
+                let large: Vec<u8> = Vec::with_capacity(100_000);
+                std::hint::black_box(large);
+            });
+            std::hint::black_box(small);
+        });
+
+        let spans = spans.lock().expect("local mutex");
+        let child = &spans[0];
+        let parent = &spans[1];
+        assert!(100_000 <= child.allocated_bytes);
+        assert!(1 <= child.allocations);
+        assert!(1000 <= parent.allocated_bytes);
+        assert!(parent.allocated_bytes < 100_000);
+    }
+
     #[tokio::test]
     async fn async_contextual_spans() {
         let (_guard, spans) = set_up_tracing();
@@ -372,4 +1489,316 @@ mod test {
             assert_eq!(trace, &span.trace_id);
         }
     }
+
+    #[tokio::test]
+    async fn links() {
+        let (_guard, spans) = set_up_tracing();
+
+        let producer = tracing::info_span!("producer");
+        let producer_context = producer.span_context().expect("producer is sampled");
+        {
+            let consumer = tracing::info_span!("consumer");
+            consumer.follows_from(&producer);
+            consumer.add_link(ActionLink::new([7; 16], [7; 8]).with_trace_state("k=v"));
+        }
+        drop(producer);
+
+        let spans: Vec<ActionSpan> = spans.lock().expect("local mutex").clone();
+        assert_eq!(2, spans.len());
+        let consumer = &spans[0];
+        let producer = &spans[1];
+        assert_eq!(2, consumer.links.len());
+        assert_eq!(producer.trace_id, consumer.links[0].trace_id);
+        assert_eq!(producer.span_id, consumer.links[0].span_id);
+        assert_eq!(producer.span_id, producer_context.span_id);
+        assert_eq!([7; 16], consumer.links[1].trace_id);
+        assert_eq!("k=v", consumer.links[1].trace_state);
+    }
+
+    #[test]
+    fn event_parents() {
+        let spans: Spans = Default::default();
+        let orphans: Arc<Mutex<Vec<ActionEvent>>> = Default::default();
+        let subscriber = test_subscriber(&spans).with_orphan_event_sink(TestEventSink {
+            events: orphans.clone(),
+        });
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("before any span");
+            let explicit_parent = tracing::info_span!("explicit parent");
+            tracing::info_span!("entered").in_scope(|| {
+                tracing::info!(parent: &explicit_parent, "explicit");
+                tracing::info!(parent: None, "explicit root");
+                tracing::info!("contextual");
+            });
+        });
+
+        let orphans = orphans.lock().expect("local mutex");
+        let orphan_messages: Vec<String> = orphans
+            .iter()
+            .map(|event| format!("{:?}", event.attributes["message"]))
+            .collect();
+        assert_eq!(
+            vec![r#"String("before any span")"#, r#"String("explicit root")"#],
+            orphan_messages
+        );
+
+        let spans = spans.lock().expect("local mutex");
+        for span in spans.iter() {
+            assert_eq!(1, span.events.len(), "{span:?}");
+        }
+        let explicit_parent = spans
+            .iter()
+            .find(|s| s.metadata.expect("there is metadata").name() == "explicit parent")
+            .expect("there is an explicit parent span");
+        assert_eq!(
+            "String(\"explicit\")",
+            format!("{:?}", explicit_parent.events[0].attributes["message"])
+        );
+    }
+
+    #[test]
+    fn events_in_evicted_spans() {
+        let spans: Spans = Default::default();
+        let orphans: Arc<Mutex<Vec<ActionEvent>>> = Default::default();
+        let subscriber = test_subscriber(&spans)
+            .with_max_live_spans(1)
+            .with_orphan_event_sink(TestEventSink {
+                events: orphans.clone(),
+            });
+        let dispatch = Dispatch::new(subscriber);
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            let leaked = tracing::info_span!("leaked");
+            let _evicts_leaked = tracing::info_span!("newer");
+            tracing::info!(parent: &leaked, "after eviction");
+        });
+
+        let orphans = orphans.lock().expect("local mutex");
+        assert_eq!(1, orphans.len(), "the event is not lost");
+        assert_eq!(
+            "String(\"after eviction\")",
+            format!("{:?}", orphans[0].attributes["message"])
+        );
+        let subscriber = dispatch
+            .downcast_ref::<ActionTraceSubscriber<TestSink, LazySpanCache>>()
+            .expect("the dispatch holds the subscriber");
+        assert_eq!(0, subscriber.stats().events_dropped);
+    }
+
+    #[test]
+    fn evicting_a_span_entered_on_another_thread() {
+        let spans: Spans = Default::default();
+        let clock = MockClock::default();
+        let subscriber = test_subscriber(&spans)
+            .with_clock(clock.clone())
+            .with_filter(
+                "warn,[request{user=bob}]=debug"
+                    .parse()
+                    .expect("filter should parse"),
+            )
+            .with_max_live_spans(1);
+        let dispatch = Dispatch::new(subscriber);
+        let entered = Arc::new(Barrier::new(2));
+        let evicted = Arc::new(Barrier::new(2));
+
+        let worker = std::thread::spawn({
+            let dispatch = dispatch.clone();
+            let entered = entered.clone();
+            let evicted = evicted.clone();
+            move || {
+                tracing::dispatcher::with_default(&dispatch, || {
+                    tracing::warn_span!("request", user = "bob").in_scope(|| {
+                        entered.wait();
+                        evicted.wait();
+                        tracing::debug_span!("bob inner").in_scope(|| {});
+                    });
+                    tracing::debug_span!("after request").in_scope(|| {});
+                })
+            }
+        });
+        entered.wait();
+        clock.advance(Duration::from_secs(1));
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::warn_span!("evicts request").in_scope(|| {});
+        });
+        evicted.wait();
+        worker.join().expect("the worker should not panic");
+
+        let subscriber = dispatch
+            .downcast_ref::<ActionTraceSubscriber<TestSink, LazySpanCache>>()
+            .expect("the dispatch holds the subscriber");
+        assert_eq!(1, subscriber.stats().spans_evicted);
+        let spans = spans.lock().expect("local mutex");
+        let names: Vec<&str> = spans.iter().map(|span| span.name()).collect();
+        assert_eq!(
+            vec!["request", "evicts request", "bob inner"],
+            names,
+            "the worker's scope ends when it exits the evicted span"
+        );
+    }
+
+    #[tokio::test]
+    async fn unsampled_traces() {
+        let spans: Spans = Default::default();
+        let orphans: Arc<Mutex<Vec<ActionEvent>>> = Default::default();
+        let subscriber = test_subscriber(&spans)
+            .with_sampler(AlwaysOff)
+            .with_orphan_event_sink(TestEventSink {
+                events: orphans.clone(),
+            });
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        async {
+            tracing::info!("in an unsampled span");
+            let current = tracing::Span::current();
+            assert!(!current.is_none(), "unsampled spans are still current");
+            assert_eq!(None, current.span_context());
+            async {
+                tracing::info_span!("a sync subspan").in_scope(|| {});
+            }
+            .instrument(tracing::info_span!("a subspan"))
+            .await;
+            tokio::spawn(async { tracing::info!("in a spawned task") }.in_current_span())
+                .await
+                .expect("task should succeed");
+        }
+        .instrument(tracing::info_span!("a root"))
+        .await;
+
+        assert_eq!(0, spans.lock().expect("local mutex").len());
+        let orphans = orphans.lock().expect("local mutex");
+        assert_eq!(0, orphans.len(), "{orphans:?}");
+    }
+
+    #[test]
+    fn remote_parents() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber(&spans).with_sampler(ParentBased::new(AlwaysOff));
+        let remote_parent = SpanContext {
+            trace_id: [3; 16],
+            span_id: [4; 8],
+            trace_flags: SpanContext::SAMPLED,
+            trace_state: "k=v".to_string(),
+            is_remote: true,
+        };
+
+        let mut headers: HashMap<String, String> = HashMap::new();
+        propagation::inject(&remote_parent, &mut headers);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let server = tracing::info_span!("server", otel.parent = headers["traceparent"]);
+            server.in_scope(|| tracing::info_span!("child").in_scope(|| {}));
+            // Too late for the sampler: the span was already dropped.
+            let late = tracing::info_span!("late");
+            late.set_parent(remote_parent.clone());
+            assert_eq!(
+                None,
+                late.span_context(),
+                "unsampled spans can not be moved"
+            );
+            late.in_scope(|| {});
+        });
+
+        let spans = spans.lock().expect("local mutex");
+        assert_eq!(
+            2,
+            spans.len(),
+            "the sampled remote parent overrides the root sampler"
+        );
+        let (child, server) = (&spans[0], &spans[1]);
+        assert_eq!([3; 16], server.trace_id);
+        assert_eq!(Some([4; 8]), server.parent_span_id);
+        assert!(server.parent_is_remote);
+        assert_eq!(
+            0,
+            server.attributes.len(),
+            "otel.parent is not an attribute"
+        );
+        assert_eq!([3; 16], child.trace_id);
+        assert_eq!(Some(server.span_id), child.parent_span_id);
+        assert!(!child.parent_is_remote);
+    }
+
+    #[test]
+    fn set_parent() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber(&spans);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let server = tracing::info_span!("server");
+            server.set_parent(SpanContext {
+                trace_id: [3; 16],
+                span_id: [4; 8],
+                trace_flags: SpanContext::SAMPLED,
+                trace_state: "k=v".to_string(),
+                is_remote: true,
+            });
+            server.in_scope(|| tracing::info_span!("child").in_scope(|| {}));
+        });
+
+        let spans = spans.lock().expect("local mutex");
+        assert_eq!(2, spans.len());
+        let (child, server) = (&spans[0], &spans[1]);
+        assert_eq!([3; 16], server.trace_id);
+        assert_eq!(Some([4; 8]), server.parent_span_id);
+        assert!(server.parent_is_remote);
+        assert_eq!([3; 16], child.trace_id);
+        assert_eq!(Some(server.span_id), child.parent_span_id);
+        assert_eq!("k=v", child.trace_state);
+    }
+
+    #[test]
+    fn unsampled_remote_parents() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber(&spans).with_sampler(ParentBased::new(AlwaysOn));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let server = tracing::info_span!("server");
+            server.set_parent(SpanContext {
+                trace_id: [3; 16],
+                span_id: [4; 8],
+                trace_flags: 0,
+                trace_state: Default::default(),
+                is_remote: true,
+            });
+            server.in_scope(|| tracing::info_span!("child").in_scope(|| {}));
+        });
+
+        assert_eq!(0, spans.lock().expect("local mutex").len());
+    }
+
+    #[test]
+    fn set_parent_samples_with_recorded_values() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber(&spans).with_sampler(SampleBobsRemoteParents);
+        let remote_parent = SpanContext {
+            trace_id: [3; 16],
+            span_id: [4; 8],
+            trace_flags: SpanContext::SAMPLED,
+            trace_state: Default::default(),
+            is_remote: true,
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            let bob = tracing::info_span!("request", user = tracing::field::Empty);
+            bob.record("user", "bob");
+            bob.set_parent(remote_parent.clone());
+            let alice = tracing::info_span!("request", user = "alice");
+            alice.set_parent(remote_parent.clone());
+            assert_eq!(
+                Some(0),
+                alice.span_context().map(|context| context.trace_flags),
+                "alice is kept for her children, but not sampled"
+            );
+        });
+
+        let spans = spans.lock().expect("local mutex");
+        assert_eq!(1, spans.len());
+        assert_eq!(
+            Some(&AttributeValue::String("bob".to_string())),
+            spans[0].attributes.get("user")
+        );
+        assert_eq!([3; 16], spans[0].trace_id);
+    }
 }
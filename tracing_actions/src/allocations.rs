@@ -0,0 +1,135 @@
+//! Count heap allocations per span.
+//!
+//! Install the `CountingAllocator` as your global allocator and turn on
+//! `ActionTraceSubscriber::with_allocation_counts()`. Allocations are counted per thread,
+//! and charged to the innermost entered span on that thread when it is exited.
+//! The `ActionTraceLayer` does not count allocations.
+//! ```rust
+//! use tracing_actions::allocations::CountingAllocator;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator = CountingAllocator::system();
+//! ```
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    ops::Sub,
+};
+
+/// Wraps a global allocator, counting each thread's allocations.
+///
+/// Counting only touches a thread local, so it never calls back into `tracing` while allocating.
+#[derive(Debug, Default)]
+pub struct CountingAllocator<A = System> {
+    allocator: A,
+}
+
+impl CountingAllocator<System> {
+    /// Count allocations made with the system allocator.
+    pub const fn system() -> Self {
+        Self::new(System)
+    }
+}
+
+impl<A> CountingAllocator<A> {
+    /// Count allocations made with `allocator`.
+    pub const fn new(allocator: A) -> Self {
+        Self { allocator }
+    }
+}
+
+/// How many allocations a thread has made, and how many bytes they asked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Allocations {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+impl Sub for Allocations {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            count: self.count.wrapping_sub(rhs.count),
+            bytes: self.bytes.wrapping_sub(rhs.bytes),
+        }
+    }
+}
+
+thread_local! {
+    // const and without Drop, so the thread local itself never allocates.
+    static ALLOCATIONS: Cell<Allocations> = const {
+        Cell::new(Allocations { count: 0, bytes: 0 })
+    };
+}
+
+/// The allocations this thread has made so far. Always zero without the `CountingAllocator`.
+pub(crate) fn thread_allocations() -> Allocations {
+    ALLOCATIONS.try_with(Cell::get).unwrap_or_default()
+}
+
+fn count(size: usize) {
+    // The thread local is gone while the thread is shutting down. Those allocations are not counted.
+    let _ = ALLOCATIONS.try_with(|allocations| {
+        let Allocations { count, bytes } = allocations.get();
+        allocations.set(Allocations {
+            count: count.wrapping_add(1),
+            bytes: bytes.wrapping_add(size as u64),
+        })
+    });
+}
+
+// Safety: every call is passed straight through to the wrapped allocator.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        self.allocator.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.allocator.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        self.allocator.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // Only the growth is new memory. Shrinking counts the call, but no bytes.
+        count(new_size.saturating_sub(layout.size()));
+        self.allocator.realloc(ptr, layout, new_size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{thread_allocations, CountingAllocator};
+
+    // This counts allocations for every test in the crate, like an application would.
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator::system();
+
+    #[test]
+    fn counts_this_thread() {
+        let before = thread_allocations();
+        let buffer: Vec<u8> = Vec::with_capacity(4096);
+        let allocated = thread_allocations() - before;
+        drop(std::hint::black_box(buffer));
+
+        assert_eq!(1, allocated.count);
+        assert_eq!(4096, allocated.bytes);
+    }
+
+    #[test]
+    fn counts_realloc_growth() {
+        let mut buffer: Vec<u8> = Vec::with_capacity(4096);
+        let before = thread_allocations();
+        buffer.reserve_exact(4096 + 1024);
+        let allocated = thread_allocations() - before;
+        drop(std::hint::black_box(buffer));
+
+        assert_eq!(1, allocated.count);
+        assert_eq!(1024, allocated.bytes);
+    }
+}
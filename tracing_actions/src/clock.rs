@@ -0,0 +1,118 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Where span and event timestamps come from.
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+/// Reads the wall clock for every timestamp. This is the default.
+///
+/// If the system clock is stepped, like by NTP, a span can end before it started.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Reads the wall clock once, and then advances it with the monotonic clock.
+///
+/// Spans always end after they start, even when the system clock is stepped. Timestamps
+/// follow the monotonic clock, so they can drift from the system clock over a long uptime.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    wall_anchor: SystemTime,
+    monotonic_anchor: Instant,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self {
+            wall_anchor: SystemTime::now(),
+            monotonic_anchor: Instant::now(),
+        }
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> SystemTime {
+        self.wall_anchor + self.monotonic_anchor.elapsed()
+    }
+}
+
+/// A clock that only moves when you move it, for tests that assert exact timestamps and durations.
+///
+/// Clones share the same time, so keep one to advance after you give one to the subscriber.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl MockClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self
+            .now
+            .lock()
+            .expect("mock clock mutex should not be poisoned") += duration;
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self
+            .now
+            .lock()
+            .expect("mock clock mutex should not be poisoned") = now;
+    }
+}
+
+impl Default for MockClock {
+    /// Starts at the unix epoch.
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self
+            .now
+            .lock()
+            .expect("mock clock mutex should not be poisoned")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use super::{Clock, MonotonicClock};
+
+    #[test]
+    fn monotonic_clock() {
+        let clock = MonotonicClock::default();
+        let first = clock.now();
+        let drift = SystemTime::now()
+            .duration_since(first)
+            .expect("the clock starts at the wall clock time");
+        assert!(drift < Duration::from_secs(1));
+
+        std::thread::sleep(Duration::from_millis(2));
+        let second = clock.now();
+        assert!(
+            Duration::from_millis(2) <= second.duration_since(first).expect("time moves forward")
+        );
+
+        let copy = clock;
+        assert!(second <= copy.now(), "copies share the anchor");
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+/// Makes the ids of new traces and spans. Ids should not be all zeros, which is invalid.
+pub trait IdGenerator {
+    fn new_trace_id(&self) -> [u8; 16];
+    fn new_span_id(&self) -> [u8; 8];
+}
+
+/// Random ids from a fast, non-cryptographic generator per thread. This is the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadLocalRandom;
+
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+impl ThreadLocalRandom {
+    fn random<const N: usize>() -> [u8; N] {
+        RNG.with(|rng| {
+            let mut rng = rng.borrow_mut();
+            let mut id = [0; N];
+            while id == [0; N] {
+                rng.fill(&mut id[..]);
+            }
+            id
+        })
+    }
+}
+
+impl IdGenerator for ThreadLocalRandom {
+    fn new_trace_id(&self) -> [u8; 16] {
+        Self::random()
+    }
+
+    fn new_span_id(&self) -> [u8; 8] {
+        Self::random()
+    }
+}
+
+/// The same sequence of ids for the same seed, for tests that assert on ids.
+///
+/// Ids are handed out in the order they are asked for, so use one thread if you need stable ids.
+#[derive(Debug)]
+pub struct Seeded {
+    state: AtomicU64,
+}
+
+impl Seeded {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// splitmix64: <https://prng.di.unimi.it/splitmix64.c>
+    fn next(&self) -> u64 {
+        const GOLDEN_GAMMA: u64 = 0x9e3779b97f4a7c15;
+        let mut z = self
+            .state
+            .fetch_add(GOLDEN_GAMMA, Ordering::Relaxed)
+            .wrapping_add(GOLDEN_GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_non_zero(&self) -> u64 {
+        loop {
+            let next = self.next();
+            if next != 0 {
+                return next;
+            }
+        }
+    }
+}
+
+impl IdGenerator for Seeded {
+    fn new_trace_id(&self) -> [u8; 16] {
+        let mut trace_id = [0; 16];
+        trace_id[..8].copy_from_slice(&self.next().to_be_bytes());
+        trace_id[8..].copy_from_slice(&self.next_non_zero().to_be_bytes());
+        trace_id
+    }
+
+    fn new_span_id(&self) -> [u8; 8] {
+        self.next_non_zero().to_be_bytes()
+    }
+}
+
+/// Trace ids that AWS X-Ray accepts: the first 4 bytes are the trace's start time in epoch
+/// seconds, and the other 12 are random.
+/// See <https://docs.aws.amazon.com/xray/latest/devguide/xray-api-sendingdata.html#xray-api-traceids>.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XRay;
+
+impl IdGenerator for XRay {
+    fn new_trace_id(&self) -> [u8; 16] {
+        let epoch_seconds = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as u32;
+        let mut trace_id: [u8; 16] = ThreadLocalRandom::random();
+        trace_id[..4].copy_from_slice(&epoch_seconds.to_be_bytes());
+        trace_id
+    }
+
+    fn new_span_id(&self) -> [u8; 8] {
+        ThreadLocalRandom::random()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+
+    use super::{IdGenerator, Seeded, ThreadLocalRandom, XRay};
+
+    #[test]
+    fn seeded_ids_repeat() {
+        let (a, b) = (Seeded::new(42), Seeded::new(42));
+        for _ in 0..10 {
+            assert_eq!(a.new_trace_id(), b.new_trace_id());
+            assert_eq!(a.new_span_id(), b.new_span_id());
+        }
+        assert_ne!(Seeded::new(1).new_span_id(), Seeded::new(2).new_span_id());
+    }
+
+    #[test]
+    fn random_ids_differ() {
+        assert_ne!(
+            ThreadLocalRandom.new_trace_id(),
+            ThreadLocalRandom.new_trace_id()
+        );
+        assert_ne!(ThreadLocalRandom.new_span_id(), [0; 8]);
+    }
+
+    #[test]
+    fn xray_trace_ids_start_with_the_time() {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("now is after the epoch")
+            .as_secs();
+        let trace_id = XRay.new_trace_id();
+        let seconds = u32::from_be_bytes([trace_id[0], trace_id[1], trace_id[2], trace_id[3]]);
+        assert!((now as i64 - seconds as i64).abs() <= 1);
+    }
+}
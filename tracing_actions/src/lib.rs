@@ -43,16 +43,67 @@
 //! // Now the rest of your application will k-log ActionSpans.
 //! ```
 //!
+//! ## Alongside other layers
+//! If you also want console logs or an `EnvFilter`, use the `ActionTraceLayer` with
+//! a `tracing_subscriber` registry instead of the standalone subscriber.
+//! ```rust
+//! use tracing_subscriber::prelude::*;
+//!
+//! struct NoSink;
+//! impl tracing_actions::TraceSink for NoSink {
+//!     fn sink_trace(&self, _trace: &mut tracing_actions::ActionSpan) {}
+//! }
+//!
+//! let subscriber = tracing_subscriber::registry()
+//!     .with(tracing_subscriber::filter::LevelFilter::DEBUG)
+//!     .with(tracing_actions::ActionTraceLayer::new(
+//!         NoSink,
+//!         tracing_actions::span_constructor::LazySpanCache::default(),
+//!     ));
+//! tracing::subscriber::set_global_default(subscriber)
+//!     .expect("I should be able to set the global trace subscriber");
+//! ```
+//!
 
 mod action_span;
+mod action_trace_layer;
 mod action_trace_subscriber;
+mod cpu_time;
+mod source_attributes;
+mod span_context;
+mod span_ext;
+mod span_limits;
+mod span_store;
+mod static_metadata;
+mod stats;
+mod trace_filter;
+
+#[cfg(test)]
+mod test_support;
 
+pub mod allocations;
+pub mod clock;
+pub mod id_generator;
+pub mod propagation;
+pub mod sampler;
 pub mod span_constructor;
+pub mod tail_sampling;
 
 pub use action_span::ActionEvent;
+pub use action_span::ActionLink;
 pub use action_span::ActionSpan;
 pub use action_span::AttributeValue;
 pub use action_span::SpanStatus;
 pub use action_span::TraceKind;
+pub use action_trace_layer::ActionTraceLayer;
 pub use action_trace_subscriber::ActionTraceSubscriber;
+pub use action_trace_subscriber::EventSink;
 pub use action_trace_subscriber::TraceSink;
+pub use span_context::SpanContext;
+pub use span_ext::ActionSpanExt;
+pub use span_limits::SpanLimits;
+pub use stats::SpanCacheStats;
+pub use stats::SubscriberStats;
+pub use trace_filter::FilterParseError;
+pub use trace_filter::FilterReloadHandle;
+pub use trace_filter::TraceFilter;
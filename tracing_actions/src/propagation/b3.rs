@@ -0,0 +1,192 @@
+use crate::SpanContext;
+
+use super::{decode_hex, decode_hex_padded, encode_hex, Extractor, Injector, Propagator};
+
+const B3_SINGLE: &str = "b3";
+const B3_TRACE_ID: &str = "x-b3-traceid";
+const B3_SPAN_ID: &str = "x-b3-spanid";
+const B3_SAMPLED: &str = "x-b3-sampled";
+const B3_FLAGS: &str = "x-b3-flags";
+
+/// Zipkin B3 headers. See <https://github.com/openzipkin/b3-propagation>.
+///
+/// Extracting reads either the single `b3` header or the `X-B3-*` headers. Injecting writes
+/// the encoding you choose. 64-bit trace ids are zero-padded to 16 bytes.
+///
+/// B3 has no trace state, and a missing sampling decision is read as not sampled.
+#[derive(Debug, Clone, Copy)]
+pub struct B3 {
+    single_header: bool,
+}
+
+impl B3 {
+    /// Inject the single `b3: {trace id}-{span id}-{sampled}` header.
+    pub fn single_header() -> Self {
+        Self {
+            single_header: true,
+        }
+    }
+
+    /// Inject `X-B3-TraceId`, `X-B3-SpanId` and `X-B3-Sampled` headers.
+    pub fn multi_header() -> Self {
+        Self {
+            single_header: false,
+        }
+    }
+}
+
+impl Propagator for B3 {
+    fn inject(&self, span_context: &SpanContext, injector: &mut dyn Injector) {
+        if !span_context.is_valid() {
+            return;
+        }
+        let trace_id = encode_hex(&span_context.trace_id);
+        let span_id = encode_hex(&span_context.span_id);
+        let sampled = if span_context.is_sampled() { "1" } else { "0" };
+        if self.single_header {
+            injector.set(B3_SINGLE, format!("{trace_id}-{span_id}-{sampled}"));
+        } else {
+            injector.set(B3_TRACE_ID, trace_id);
+            injector.set(B3_SPAN_ID, span_id);
+            injector.set(B3_SAMPLED, sampled.to_string());
+        }
+    }
+
+    fn extract(&self, extractor: &dyn Extractor) -> Option<SpanContext> {
+        let span_context = match extractor.get(B3_SINGLE) {
+            Some(single) => extract_single(single.trim())?,
+            None => extract_multi(extractor)?,
+        };
+        span_context.is_valid().then_some(span_context)
+    }
+}
+
+/// `{trace id}-{span id}[-{sampled}[-{parent span id}]]`. A lone sampling state has no ids to continue.
+fn extract_single(b3: &str) -> Option<SpanContext> {
+    let mut parts = b3.split('-');
+    let trace_id = decode_trace_id(parts.next()?)?;
+    let span_id = decode_hex::<8>(parts.next()?)?;
+    let sampled = match parts.next() {
+        Some("1" | "d") => true,
+        Some("0") | None => false,
+        Some(_) => return None,
+    };
+    if let Some(parent_span_id) = parts.next() {
+        decode_hex::<8>(parent_span_id)?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(remote_context(trace_id, span_id, sampled))
+}
+
+fn extract_multi(extractor: &dyn Extractor) -> Option<SpanContext> {
+    let trace_id = decode_trace_id(extractor.get(B3_TRACE_ID)?.trim())?;
+    let span_id = decode_hex::<8>(extractor.get(B3_SPAN_ID)?.trim())?;
+    // The debug flag implies sampled.
+    let sampled = extractor.get(B3_FLAGS).map(str::trim) == Some("1")
+        || matches!(extractor.get(B3_SAMPLED).map(str::trim), Some("1" | "true"));
+    Some(remote_context(trace_id, span_id, sampled))
+}
+
+/// B3 trace ids are 64 or 128 bits.
+fn decode_trace_id(hex: &str) -> Option<[u8; 16]> {
+    match hex.len() {
+        16 | 32 => decode_hex_padded(hex),
+        _ => None,
+    }
+}
+
+fn remote_context(trace_id: [u8; 16], span_id: [u8; 8], sampled: bool) -> SpanContext {
+    SpanContext {
+        trace_id,
+        span_id,
+        trace_flags: if sampled { SpanContext::SAMPLED } else { 0 },
+        trace_state: Default::default(),
+        is_remote: true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        propagation::{Propagator, B3},
+        SpanContext,
+    };
+
+    fn headers(headers: &[(&str, &str)]) -> HashMap<String, String> {
+        headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let context = SpanContext {
+            trace_id: [0x4b; 16],
+            span_id: [0x0a; 8],
+            trace_flags: SpanContext::SAMPLED,
+            trace_state: Default::default(),
+            is_remote: true,
+        };
+        for propagator in [B3::single_header(), B3::multi_header()] {
+            let mut headers = HashMap::new();
+            propagator.inject(&context, &mut headers);
+            assert_eq!(Some(context.clone()), propagator.extract(&headers));
+        }
+
+        let mut headers = HashMap::new();
+        B3::single_header().inject(&context, &mut headers);
+        assert_eq!(
+            "4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b-0a0a0a0a0a0a0a0a-1",
+            headers["b3"]
+        );
+    }
+
+    #[test]
+    fn short_trace_ids() {
+        let single = headers(&[("b3", "000000000000004b-000000000000000a-1-0000000000000001")]);
+        let multi = headers(&[
+            ("x-b3-traceid", "000000000000004b"),
+            ("x-b3-spanid", "000000000000000a"),
+            ("x-b3-flags", "1"),
+        ]);
+        for headers in [single, multi] {
+            let context = B3::single_header()
+                .extract(&headers)
+                .expect("context should extract");
+            let mut trace_id = [0; 16];
+            trace_id[15] = 0x4b;
+            assert_eq!(trace_id, context.trace_id);
+            assert_eq!([0, 0, 0, 0, 0, 0, 0, 0x0a], context.span_id);
+            assert!(context.is_sampled());
+        }
+    }
+
+    #[test]
+    fn invalid_headers() {
+        for b3 in [
+            "0",
+            "d",
+            "4b4b4b4b4b4b4b4b4b4b-0a0a0a0a0a0a0a0a-1",
+            "4b4b4b4b4b4b4b4b-0000000000000000-1",
+            "4b4b4b4b4b4b4b4b-0a0a0a0a0a0a0a0a-x",
+        ] {
+            assert_eq!(
+                None,
+                B3::multi_header().extract(&headers(&[("b3", b3)])),
+                "{b3}"
+            );
+        }
+        let unsampled = B3::multi_header()
+            .extract(&headers(&[
+                ("x-b3-traceid", "4b4b4b4b4b4b4b4b"),
+                ("x-b3-spanid", "0a0a0a0a0a0a0a0a"),
+            ]))
+            .expect("context should extract");
+        assert!(!unsampled.is_sampled());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::SpanContext;
+
+use super::{Extractor, Injector, Propagator};
+
+/// Several propagators at once, for services whose callers do not agree on a format.
+///
+/// Extracting returns the first context found, in the order the propagators were added.
+/// Injecting writes every format.
+#[derive(Default)]
+pub struct Composite {
+    propagators: Vec<Box<dyn Propagator + Send + Sync>>,
+}
+
+impl Composite {
+    pub fn with(mut self, propagator: impl Propagator + Send + Sync + 'static) -> Self {
+        self.propagators.push(Box::new(propagator));
+        self
+    }
+}
+
+impl Propagator for Composite {
+    fn inject(&self, span_context: &SpanContext, injector: &mut dyn Injector) {
+        for propagator in &self.propagators {
+            propagator.inject(span_context, injector);
+        }
+    }
+
+    fn extract(&self, extractor: &dyn Extractor) -> Option<SpanContext> {
+        self.propagators
+            .iter()
+            .find_map(|propagator| propagator.extract(extractor))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        propagation::{Composite, Jaeger, Propagator, TraceContext, B3},
+        SpanContext,
+    };
+
+    #[test]
+    fn all_formats() {
+        let propagator = Composite::default()
+            .with(TraceContext)
+            .with(B3::multi_header())
+            .with(Jaeger);
+        let context = SpanContext {
+            trace_id: [0x4b; 16],
+            span_id: [0x0a; 8],
+            trace_flags: SpanContext::SAMPLED,
+            trace_state: Default::default(),
+            is_remote: true,
+        };
+
+        let mut headers = HashMap::new();
+        propagator.inject(&context, &mut headers);
+        let mut keys: Vec<&str> = headers.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(
+            vec![
+                "traceparent",
+                "uber-trace-id",
+                "x-b3-sampled",
+                "x-b3-spanid",
+                "x-b3-traceid"
+            ],
+            keys
+        );
+
+        let jaeger_only = HashMap::from([(
+            "uber-trace-id".to_string(),
+            headers["uber-trace-id"].clone(),
+        )]);
+        assert_eq!(Some(context), propagator.extract(&jaeger_only));
+        assert_eq!(None, propagator.extract(&HashMap::new()));
+    }
+}
@@ -0,0 +1,43 @@
+use super::{Extractor, Injector};
+
+impl Injector for http::HeaderMap {
+    fn set(&mut self, key: &str, value: String) {
+        match (
+            http::header::HeaderName::from_bytes(key.as_bytes()),
+            http::header::HeaderValue::try_from(value),
+        ) {
+            (Ok(name), Ok(value)) => {
+                self.insert(name, value);
+            }
+            _ => log::debug!("could not inject header {key}"),
+        }
+    }
+}
+
+impl Extractor for http::HeaderMap {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|value| value.to_str().ok())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        propagation::{extract, inject},
+        SpanContext,
+    };
+
+    #[test]
+    fn header_map_round_trip() {
+        let context = SpanContext {
+            trace_id: [0x4b; 16],
+            span_id: [0x0a; 8],
+            trace_flags: SpanContext::SAMPLED,
+            trace_state: "vendor=value".to_string(),
+            is_remote: true,
+        };
+        let mut headers = http::HeaderMap::new();
+        inject(&context, &mut headers);
+        assert_eq!(Some(context), extract(&headers));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::SpanContext;
+
+use super::{decode_hex_padded, encode_hex, Extractor, Injector, Propagator};
+
+const UBER_TRACE_ID: &str = "uber-trace-id";
+const SAMPLED_FLAG: u8 = 0x01;
+const DEBUG_FLAG: u8 = 0x02;
+
+/// Jaeger's `uber-trace-id: {trace id}:{span id}:{parent span id}:{flags}` header.
+/// See <https://www.jaegertracing.io/docs/latest/client-libraries/#propagation-format>.
+///
+/// Jaeger ids may omit leading zeros, and 64-bit trace ids are zero-padded to 16 bytes.
+/// Jaeger baggage is not read.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Jaeger;
+
+impl Propagator for Jaeger {
+    fn inject(&self, span_context: &SpanContext, injector: &mut dyn Injector) {
+        if !span_context.is_valid() {
+            return;
+        }
+        let flags = if span_context.is_sampled() {
+            SAMPLED_FLAG
+        } else {
+            0
+        };
+        injector.set(
+            UBER_TRACE_ID,
+            format!(
+                "{}:{}:0:{flags:x}",
+                encode_hex(&span_context.trace_id),
+                encode_hex(&span_context.span_id),
+            ),
+        );
+    }
+
+    fn extract(&self, extractor: &dyn Extractor) -> Option<SpanContext> {
+        // Some clients url-encode the header.
+        let header = extractor.get(UBER_TRACE_ID)?.trim().replace("%3A", ":");
+        let mut parts = header.split(':');
+        let trace_id = decode_hex_padded::<16>(parts.next()?)?;
+        let span_id = decode_hex_padded::<8>(parts.next()?)?;
+        let _deprecated_parent_span_id = parts.next()?;
+        let flags = u8::from_str_radix(parts.next()?, 16).ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let span_context = SpanContext {
+            trace_id,
+            span_id,
+            trace_flags: if flags & (SAMPLED_FLAG | DEBUG_FLAG) != 0 {
+                SpanContext::SAMPLED
+            } else {
+                0
+            },
+            trace_state: Default::default(),
+            is_remote: true,
+        };
+        span_context.is_valid().then_some(span_context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        propagation::{Jaeger, Propagator},
+        SpanContext,
+    };
+
+    #[test]
+    fn round_trip() {
+        let context = SpanContext {
+            trace_id: [0x4b; 16],
+            span_id: [0x0a; 8],
+            trace_flags: SpanContext::SAMPLED,
+            trace_state: Default::default(),
+            is_remote: true,
+        };
+        let mut headers = HashMap::new();
+        Jaeger.inject(&context, &mut headers);
+        assert_eq!(
+            "4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b:0a0a0a0a0a0a0a0a:0:1",
+            headers["uber-trace-id"]
+        );
+        assert_eq!(Some(context), Jaeger.extract(&headers));
+    }
+
+    #[test]
+    fn short_ids() {
+        let headers = HashMap::from([("uber-trace-id".to_string(), "4b%3Aa%3A0%3A3".to_string())]);
+        let context = Jaeger.extract(&headers).expect("context should extract");
+        let mut trace_id = [0; 16];
+        trace_id[15] = 0x4b;
+        assert_eq!(trace_id, context.trace_id);
+        assert_eq!([0, 0, 0, 0, 0, 0, 0, 0x0a], context.span_id);
+        assert!(context.is_sampled());
+
+        for invalid in ["4b:a:0", "0:a:0:1", "4b:0:0:1", "4b:a:0:1:2", "4b:a:0:zz"] {
+            let headers = HashMap::from([("uber-trace-id".to_string(), invalid.to_string())]);
+            assert_eq!(None, Jaeger.extract(&headers), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Carry traces across process boundaries in request headers.
+//!
+//! Outgoing requests get the current span's context with `inject()`. Incoming requests can
+//! continue the caller's trace with the context from `extract()`. These use W3C trace context
+//! (`traceparent` and `tracestate`): <https://www.w3.org/TR/trace-context/>.
+//!
+//! Headers can be any `Injector` or `Extractor`, like a `HashMap<String, String>`. Turn on the
+//! `http` feature for `http::HeaderMap`.
+//! ```rust
+//! use std::collections::HashMap;
+//! use tracing_actions::{propagation, ActionSpanExt};
+//!
+//! let span = tracing::info_span!("outgoing request");
+//! let mut headers: HashMap<String, String> = HashMap::new();
+//! if let Some(context) = span.span_context() {
+//!     propagation::inject(&context, &mut headers);
+//! }
+//! ```
+//!
+//! For services that speak other formats, use a `Propagator`. A `Composite` accepts whichever
+//! format the caller sent, and writes all of them.
+//! ```rust
+//! use tracing_actions::propagation::{Composite, Jaeger, Propagator, TraceContext, B3};
+//!
+//! let propagator = Composite::default()
+//!     .with(TraceContext)
+//!     .with(B3::multi_header())
+//!     .with(Jaeger);
+//! # let request_headers: std::collections::HashMap<String, String> = Default::default();
+//! let parent = propagator.extract(&request_headers);
+//! ```
+
+use std::collections::HashMap;
+
+use tracing::{field::Visit, span::Attributes};
+
+use crate::{action_span::OTEL_PARENT, SpanContext};
+
+mod b3;
+mod composite;
+#[cfg(feature = "http")]
+mod header_map;
+mod jaeger;
+mod trace_context;
+
+pub use b3::B3;
+pub use composite::Composite;
+pub use jaeger::Jaeger;
+pub use trace_context::TraceContext;
+
+/// Somewhere to write propagation headers, like an outgoing request's headers.
+pub trait Injector {
+    fn set(&mut self, key: &str, value: String);
+}
+
+/// Somewhere to read propagation headers from, like an incoming request's headers.
+pub trait Extractor {
+    fn get(&self, key: &str) -> Option<&str>;
+}
+
+/// A header format for span contexts.
+pub trait Propagator {
+    /// Write `span_context` to `injector`. Invalid contexts are not written.
+    fn inject(&self, span_context: &SpanContext, injector: &mut dyn Injector);
+
+    /// Read a remote span context. None when there is no context in this format, or it is not valid.
+    fn extract(&self, extractor: &dyn Extractor) -> Option<SpanContext>;
+}
+
+/// Write `span_context` as W3C `traceparent` and `tracestate` headers. Invalid contexts are not written.
+pub fn inject(span_context: &SpanContext, injector: &mut impl Injector) {
+    TraceContext.inject(span_context, injector)
+}
+
+/// Read a remote span context from W3C `traceparent` and `tracestate` headers.
+///
+/// Returns None when there is no `traceparent`, or when it is not valid.
+pub fn extract(extractor: &impl Extractor) -> Option<SpanContext> {
+    TraceContext.extract(extractor)
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push(DIGITS[(byte >> 4) as usize] as char);
+        hex.push(DIGITS[(byte & 0xf) as usize] as char);
+    }
+    hex
+}
+
+/// Only exactly-sized, lowercase hex is accepted.
+pub(crate) fn decode_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    fn digit(c: u8) -> Option<u8> {
+        match c {
+            b'0'..=b'9' => Some(c - b'0'),
+            b'a'..=b'f' => Some(c - b'a' + 10),
+            _ => None,
+        }
+    }
+    let hex = hex.as_bytes();
+    if hex.len() != N * 2 {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(hex.chunks_exact(2)) {
+        *byte = digit(pair[0])? << 4 | digit(pair[1])?;
+    }
+    Some(bytes)
+}
+
+/// Like `decode_hex`, but shorter ids are left-padded with zeros: a 64-bit trace id becomes
+/// the low 8 bytes of a 16-byte id.
+pub(crate) fn decode_hex_padded<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.is_empty() || N * 2 < hex.len() {
+        return None;
+    }
+    let padded = format!("{hex:0>width$}", width = N * 2);
+    decode_hex(&padded)
+}
+
+/// The remote parent from a span's `otel.parent` field, a W3C `traceparent`.
+pub(crate) fn remote_parent(attributes: &Attributes) -> Option<SpanContext> {
+    attributes.metadata().fields().field(OTEL_PARENT)?;
+    let mut traceparent = Traceparent(None);
+    attributes.record(&mut traceparent);
+    let traceparent = traceparent.0?;
+    let parent = TraceContext.extract(&HashMap::from([("traceparent".to_string(), traceparent)]));
+    if parent.is_none() {
+        log::debug!("otel.parent is not a valid traceparent - ignoring it");
+    }
+    parent
+}
+
+struct Traceparent(Option<String>);
+
+impl Visit for Traceparent {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        if field.name() == OTEL_PARENT {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == OTEL_PARENT {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+/// For message queue headers and other string maps. Keys are lowercase.
+impl Injector for HashMap<String, String> {
+    fn set(&mut self, key: &str, value: String) {
+        self.insert(key.to_string(), value);
+    }
+}
+
+impl Extractor for HashMap<String, String> {
+    fn get(&self, key: &str) -> Option<&str> {
+        HashMap::get(self, key).map(String::as_str)
+    }
+}
@@ -0,0 +1,119 @@
+use crate::SpanContext;
+
+use super::{decode_hex, encode_hex, Extractor, Injector, Propagator};
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+const SUPPORTED_VERSION: u8 = 0;
+
+/// W3C trace context: `traceparent` and `tracestate` headers.
+/// See <https://www.w3.org/TR/trace-context/>.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceContext;
+
+impl Propagator for TraceContext {
+    fn inject(&self, span_context: &SpanContext, injector: &mut dyn Injector) {
+        if !span_context.is_valid() {
+            return;
+        }
+        injector.set(
+            TRACEPARENT,
+            format!(
+                "{:02x}-{}-{}-{:02x}",
+                SUPPORTED_VERSION,
+                encode_hex(&span_context.trace_id),
+                encode_hex(&span_context.span_id),
+                span_context.trace_flags,
+            ),
+        );
+        if !span_context.trace_state.is_empty() {
+            injector.set(TRACESTATE, span_context.trace_state.clone());
+        }
+    }
+
+    fn extract(&self, extractor: &dyn Extractor) -> Option<SpanContext> {
+        let traceparent = extractor.get(TRACEPARENT)?.trim();
+        let mut parts = traceparent.split('-');
+        let version = decode_hex::<1>(parts.next()?)?[0];
+        let trace_id = decode_hex::<16>(parts.next()?)?;
+        let span_id = decode_hex::<8>(parts.next()?)?;
+        let trace_flags = decode_hex::<1>(parts.next()?)?[0];
+        // Version 0 has exactly 4 parts. Later versions may add more, and we should still read the first 4.
+        if version == 0xff || (version == SUPPORTED_VERSION && parts.next().is_some()) {
+            return None;
+        }
+
+        let span_context = SpanContext {
+            trace_id,
+            span_id,
+            trace_flags: trace_flags & SpanContext::SAMPLED,
+            trace_state: extractor
+                .get(TRACESTATE)
+                .map(|trace_state| trace_state.trim().to_string())
+                .unwrap_or_default(),
+            is_remote: true,
+        };
+        span_context.is_valid().then_some(span_context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        propagation::{extract, inject},
+        SpanContext,
+    };
+
+    #[test]
+    fn round_trip() {
+        let context = SpanContext {
+            trace_id: [0x4b; 16],
+            span_id: [0x0a; 8],
+            trace_flags: SpanContext::SAMPLED,
+            trace_state: "vendor=value".to_string(),
+            is_remote: false,
+        };
+        let mut headers: HashMap<String, String> = HashMap::new();
+        inject(&context, &mut headers);
+        assert_eq!(
+            "00-4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b-0a0a0a0a0a0a0a0a-01",
+            headers["traceparent"]
+        );
+        assert_eq!("vendor=value", headers["tracestate"]);
+
+        let extracted = extract(&headers).expect("context should extract");
+        assert_eq!(
+            SpanContext {
+                is_remote: true,
+                ..context
+            },
+            extracted
+        );
+    }
+
+    #[test]
+    fn invalid_traceparents() {
+        for traceparent in [
+            "",
+            "00-4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b-0a0a0a0a0a0a0a0a",
+            "00-00000000000000000000000000000000-0a0a0a0a0a0a0a0a-01",
+            "00-4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b-0000000000000000-01",
+            "00-4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B-0a0a0a0a0a0a0a0a-01",
+            "00-4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b-0a0a0a0a0a0a0a0a-01-extra",
+            "ff-4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b-0a0a0a0a0a0a0a0a-01",
+        ] {
+            let headers = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+            assert_eq!(None, extract(&headers), "{traceparent}");
+        }
+
+        // Future versions may have more fields
+        let headers = HashMap::from([(
+            "traceparent".to_string(),
+            "01-4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b4b-0a0a0a0a0a0a0a0a-00-extra".to_string(),
+        )]);
+        let extracted = extract(&headers).expect("context should extract");
+        assert!(!extracted.is_sampled());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use tracing::{
+    field::{Field, Value},
+    span::Attributes,
+    Metadata,
+};
+
+use crate::{ActionSpan, AttributeValue, SpanContext};
+
+/// What to do with a new trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingDecision {
+    /// Don't record the trace. Nothing is sent to your sink, and its spans are not constructed or stored.
+    Drop,
+    /// Record the trace and send its spans to your sink.
+    RecordAndSample,
+}
+
+/// What a `Sampler` knows about a new root span, or a span given a remote parent.
+pub struct SamplingParameters<'a> {
+    pub metadata: &'static Metadata<'static>,
+    /// When the sampler is consulted again by `ActionSpanExt::set_parent()`, these are the
+    /// values the span has recorded so far.
+    pub attributes: &'a Attributes<'a>,
+    /// The id the new trace will have if it is sampled.
+    pub trace_id: &'a [u8; 16],
+    /// The remote span this trace continues, from the span's `otel.parent` field or
+    /// `ActionSpanExt::set_parent()`.
+    pub parent: Option<&'a SpanContext>,
+}
+
+/// Decides whether a new trace is recorded, when its root span is created or given a remote parent.
+///
+/// The decision applies to the whole trace: children of an unsampled span are not sampled
+/// either, and cost very little.
+pub trait Sampler {
+    fn should_sample(&self, parameters: &SamplingParameters<'_>) -> SamplingDecision;
+}
+
+/// Records every trace. This is the default.
+pub struct AlwaysOn;
+impl Sampler for AlwaysOn {
+    fn should_sample(&self, _parameters: &SamplingParameters<'_>) -> SamplingDecision {
+        SamplingDecision::RecordAndSample
+    }
+}
+
+/// Records no traces.
+pub struct AlwaysOff;
+impl Sampler for AlwaysOff {
+    fn should_sample(&self, _parameters: &SamplingParameters<'_>) -> SamplingDecision {
+        SamplingDecision::Drop
+    }
+}
+
+/// Records a fraction of traces, chosen by trace id.
+///
+/// Because the decision is a function of the trace id, every service that uses the same
+/// ratio makes the same decision for the same trace.
+pub struct TraceIdRatio {
+    threshold: u64,
+}
+impl TraceIdRatio {
+    /// `ratio` is clamped to 0..=1. 0.01 records about 1 trace in 100.
+    pub fn new(ratio: f64) -> Self {
+        Self {
+            threshold: ratio_threshold(ratio),
+        }
+    }
+}
+impl Sampler for TraceIdRatio {
+    fn should_sample(&self, parameters: &SamplingParameters<'_>) -> SamplingDecision {
+        if trace_id_is_below(parameters.trace_id, self.threshold) {
+            SamplingDecision::RecordAndSample
+        } else {
+            SamplingDecision::Drop
+        }
+    }
+}
+
+pub(crate) fn ratio_threshold(ratio: f64) -> u64 {
+    let ratio = ratio.clamp(0.0, 1.0);
+    if ratio == 1.0 {
+        u64::MAX
+    } else {
+        (ratio * u64::MAX as f64) as u64
+    }
+}
+
+pub(crate) fn trace_id_is_below(trace_id: &[u8; 16], threshold: u64) -> bool {
+    // The rightmost bytes are the random part of a w3c trace id.
+    let mut random = [0; 8];
+    random.copy_from_slice(&trace_id[8..]);
+    threshold == u64::MAX || u64::from_be_bytes(random) < threshold
+}
+
+/// Give `span` a remote parent, and ask `sampler` again with the values the span has recorded.
+pub(crate) fn set_remote_parent(
+    sampler: &dyn Sampler,
+    span: &mut ActionSpan,
+    parent: &SpanContext,
+) {
+    span.set_remote_parent(parent);
+    let Some(metadata) = span.metadata else {
+        return;
+    };
+    let decision = with_recorded_attributes(metadata, &span.attributes, |attributes| {
+        sampler.should_sample(&SamplingParameters {
+            metadata,
+            attributes,
+            trace_id: &parent.trace_id,
+            parent: Some(parent),
+        })
+    });
+    span.trace_flags = match decision {
+        SamplingDecision::Drop => 0,
+        SamplingDecision::RecordAndSample => SpanContext::SAMPLED,
+    };
+}
+
+/// tracing callsites have at most this many fields.
+const MAX_FIELDS: usize = 32;
+
+/// Rebuild a span's `Attributes` from what it recorded, for the sampler.
+fn with_recorded_attributes<T>(
+    metadata: &'static Metadata<'static>,
+    recorded: &HashMap<&'static str, AttributeValue>,
+    use_attributes: impl FnOnce(&Attributes<'_>) -> T,
+) -> T {
+    let fields: Vec<Field> = metadata.fields().iter().take(MAX_FIELDS).collect();
+    let Some(first) = fields.first() else {
+        let no_values: [(&Field, Option<&dyn Value>); 0] = [];
+        return use_attributes(&Attributes::new(
+            metadata,
+            &metadata.fields().value_set(&no_values),
+        ));
+    };
+    // A ValueSet is made from an array. The slots past the last field repeat the first one,
+    // without a value.
+    let values: [(&Field, Option<&dyn Value>); MAX_FIELDS] =
+        std::array::from_fn(|i| match fields.get(i) {
+            Some(field) => (field, recorded.get(field.name()).map(as_value)),
+            None => (first, None),
+        });
+    use_attributes(&Attributes::new(
+        metadata,
+        &metadata.fields().value_set(&values),
+    ))
+}
+
+fn as_value(value: &AttributeValue) -> &dyn Value {
+    match value {
+        AttributeValue::String(s) | AttributeValue::Error(s) => s,
+        AttributeValue::F64(f) => f,
+        AttributeValue::I64(i) => i,
+        AttributeValue::U64(u) => u,
+        AttributeValue::I128(i) => i,
+        AttributeValue::U128(u) => u,
+        AttributeValue::Bool(b) => b,
+    }
+}
+
+/// Follows the parent's sampled flag when there is a parent, and uses the `root` sampler otherwise.
+pub struct ParentBased<Root> {
+    root: Root,
+}
+impl<Root: Sampler> ParentBased<Root> {
+    pub fn new(root: Root) -> Self {
+        Self { root }
+    }
+}
+impl<Root: Sampler> Sampler for ParentBased<Root> {
+    fn should_sample(&self, parameters: &SamplingParameters<'_>) -> SamplingDecision {
+        match parameters.parent {
+            Some(parent) if parent.is_sampled() => SamplingDecision::RecordAndSample,
+            Some(_) => SamplingDecision::Drop,
+            None => self.root.should_sample(parameters),
+        }
+    }
+}
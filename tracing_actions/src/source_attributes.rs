@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing::Metadata;
+
+use crate::{ActionEvent, ActionSpan, AttributeValue, SpanLimits};
+
+/// Which opentelemetry semantic convention attributes to add to spans and events, describing
+/// where in the code they came from and which thread recorded them.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SourceAttributes {
+    /// `code.filepath`, `code.lineno`, `code.namespace` and `target`
+    pub code: bool,
+    /// `thread.id` and `thread.name`
+    pub thread: bool,
+}
+
+impl SourceAttributes {
+    /// For a span that was just started, on the thread that started it.
+    pub fn add_to_span(&self, span: &mut ActionSpan) {
+        let metadata = match span.metadata {
+            Some(metadata) => metadata,
+            None => return,
+        };
+        self.for_each(metadata, |key, value| span.add_attribute(key, value));
+    }
+
+    pub fn add_to_event(&self, event: &mut ActionEvent, limits: &SpanLimits) {
+        let metadata = event.metadata;
+        self.for_each(metadata, |key, value| {
+            event.add_attribute(key, value, limits)
+        });
+    }
+
+    fn for_each(
+        &self,
+        metadata: &'static Metadata<'static>,
+        mut add: impl FnMut(&'static str, AttributeValue),
+    ) {
+        if self.code {
+            if let Some(file) = metadata.file() {
+                add("code.filepath", AttributeValue::String(file.to_string()));
+            }
+            if let Some(line) = metadata.line() {
+                add("code.lineno", AttributeValue::I64(line as i64));
+            }
+            if let Some(module_path) = metadata.module_path() {
+                add(
+                    "code.namespace",
+                    AttributeValue::String(module_path.to_string()),
+                );
+            }
+            add(
+                "target",
+                AttributeValue::String(metadata.target().to_string()),
+            );
+        }
+        if self.thread {
+            add("thread.id", AttributeValue::I64(thread_id() as i64));
+            if let Some(name) = std::thread::current().name() {
+                add("thread.name", AttributeValue::String(name.to_string()));
+            }
+        }
+    }
+}
+
+/// std's ThreadId has no stable number, so threads are numbered as they first record something.
+fn thread_id() -> u64 {
+    static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+    }
+    THREAD_ID.with(|id| *id)
+}
@@ -1,10 +1,18 @@
-use std::sync::Mutex;
+use std::sync::{Mutex, TryLockError};
 
-use crate::ActionSpan;
+use crate::{
+    stats::{PerThreadCounters, SpanCacheCounts},
+    ActionSpan, SpanCacheStats,
+};
 
 pub trait SpanConstructor {
     fn new_span(&self) -> ActionSpan;
     fn return_span(&self, span: ActionSpan);
+
+    /// Counters for `SubscriberStats::span_cache`. All zero by default.
+    fn stats(&self) -> SpanCacheStats {
+        Default::default()
+    }
 }
 
 pub struct AlwaysNewSpanConstructor;
@@ -23,12 +31,14 @@ impl SpanConstructor for AlwaysNewSpanConstructor {
 pub struct LazySpanCache {
     span_cache_size: usize,
     span_cache: Mutex<Vec<ActionSpan>>,
+    counters: PerThreadCounters<SpanCacheCounts>,
 }
 impl LazySpanCache {
     pub fn new(span_cache_size: usize) -> Self {
         Self {
             span_cache_size,
             span_cache: Vec::with_capacity(span_cache_size).into(),
+            counters: Default::default(),
         }
     }
 }
@@ -39,18 +49,40 @@ impl Default for LazySpanCache {
 }
 impl SpanConstructor for LazySpanCache {
     fn new_span(&self) -> ActionSpan {
-        match self.span_cache.try_lock() {
+        let cached = match self.span_cache.try_lock() {
             Ok(mut lazy_win) => lazy_win.pop(),
             Err(_) => None,
+        };
+        match cached {
+            Some(span) => {
+                self.counters.count(|counts| &counts.hits);
+                span
+            }
+            None => {
+                self.counters.count(|counts| &counts.misses);
+                ActionSpan::default()
+            }
         }
-        .unwrap_or_default()
     }
 
     fn return_span(&self, span: ActionSpan) {
-        if let Ok(mut lazy_win) = self.span_cache.try_lock() {
-            if lazy_win.len() < self.span_cache_size {
-                lazy_win.push(span)
+        match self.span_cache.try_lock() {
+            Ok(mut lazy_win) => {
+                if lazy_win.len() < self.span_cache_size {
+                    lazy_win.push(span)
+                } else {
+                    self.counters.count(|counts| &counts.rejected_full);
+                }
+            }
+            Err(TryLockError::WouldBlock) => {
+                self.counters.count(|counts| &counts.rejected_contended);
             }
+            // A thread panicked while returning a span, so the cache is abandoned.
+            Err(TryLockError::Poisoned(_)) => {}
         }
     }
+
+    fn stats(&self) -> SpanCacheStats {
+        self.counters.stats()
+    }
 }
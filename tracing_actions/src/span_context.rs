@@ -0,0 +1,32 @@
+/// The identity of a span, as seen by other spans and other processes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpanContext {
+    /// The 16-byte id of the trace this span belongs to.
+    pub trace_id: [u8; 16],
+
+    /// The 8-byte id of this span.
+    pub span_id: [u8; 8],
+
+    /// W3C trace flags. Only `SpanContext::SAMPLED` is defined.
+    pub trace_flags: u8,
+
+    /// Vendor specific trace state, in w3c-trace-context format.
+    pub trace_state: String,
+
+    /// True when this context came from another process.
+    pub is_remote: bool,
+}
+
+impl SpanContext {
+    /// The trace flag for "the caller may have recorded this trace".
+    pub const SAMPLED: u8 = 0x01;
+
+    pub fn is_sampled(&self) -> bool {
+        self.trace_flags & Self::SAMPLED == Self::SAMPLED
+    }
+
+    /// All-zero trace and span ids are invalid.
+    pub fn is_valid(&self) -> bool {
+        self.trace_id != [0; 16] && self.span_id != [0; 8]
+    }
+}
@@ -0,0 +1,142 @@
+use std::{any::type_name, error::Error, time::SystemTime};
+
+use tracing::{span, Dispatch};
+
+use crate::{ActionLink, ActionSpan, SpanContext};
+
+/// Lets `ActionSpanExt` reach the live ActionSpan behind a `tracing::Span`.
+///
+/// The ActionTraceSubscriber and ActionTraceLayer hand this out from `downcast_raw`, because
+/// their concrete types depend on your sink and span constructor.
+pub(crate) struct WithActionSpan {
+    access: AccessActionSpan,
+    set_parent: SetRemoteParent,
+    now: Now,
+}
+
+type AccessActionSpan = fn(&Dispatch, &span::Id, &mut dyn FnMut(&mut ActionSpan));
+/// Setting a parent can change the sampling decision, which only the subscriber knows how to make.
+type SetRemoteParent = fn(&Dispatch, &span::Id, &SpanContext);
+/// The time from the subscriber's clock, for events added through `ActionSpanExt`.
+type Now = fn(&Dispatch) -> SystemTime;
+
+impl WithActionSpan {
+    pub fn new(access: AccessActionSpan, set_parent: SetRemoteParent, now: Now) -> Self {
+        Self {
+            access,
+            set_parent,
+            now,
+        }
+    }
+}
+
+fn with_action_span<T>(
+    span: &tracing::Span,
+    use_it: impl FnOnce(&mut ActionSpan) -> T,
+) -> Option<T> {
+    span.with_subscriber(|(id, dispatch)| {
+        let access = dispatch.downcast_ref::<WithActionSpan>()?;
+        let mut use_it = Some(use_it);
+        let mut result = None;
+        (access.access)(dispatch, id, &mut |action_span| {
+            if let Some(use_it) = use_it.take() {
+                result = Some(use_it(action_span))
+            }
+        });
+        result
+    })
+    .flatten()
+}
+
+/// ActionSpan features for `tracing::Span`s.
+///
+/// These do nothing when the span is disabled, or when the current subscriber does not record ActionSpans.
+pub trait ActionSpanExt {
+    /// Link this span to another span, like the span that produced the message you are processing.
+    /// ```rust
+    /// use tracing_actions::{ActionLink, ActionSpanExt};
+    /// # let (producer_trace_id, producer_span_id) = ([1; 16], [1; 8]);
+    ///
+    /// let span = tracing::info_span!("process message");
+    /// span.add_link(ActionLink::new(producer_trace_id, producer_span_id));
+    /// ```
+    /// Spans in the same process can use `follows_from` instead.
+    fn add_link(&self, link: ActionLink);
+
+    /// The trace and span ids of this span, for propagating to other processes with
+    /// `tracing_actions::propagation::inject()`.
+    ///
+    /// None when the span is disabled or not sampled.
+    fn span_context(&self) -> Option<SpanContext>;
+
+    /// Continue a trace from another process: this span becomes a child of `parent`, usually
+    /// a context from `tracing_actions::propagation::extract()`.
+    /// ```rust
+    /// use tracing_actions::{propagation, ActionSpanExt};
+    /// # let request_headers: std::collections::HashMap<String, String> = Default::default();
+    ///
+    /// let span = tracing::info_span!("handle request");
+    /// if let Some(parent) = propagation::extract(&request_headers) {
+    ///     span.set_parent(parent);
+    /// }
+    /// ```
+    /// Call this right after creating the span: children that already exist stay in the old trace.
+    /// Only spans that were sampled can be moved. The sampler decides again with the remote parent
+    /// and the values the span has recorded so far, and may drop the span, but it can not bring
+    /// back a span it already dropped. For a `ParentBased`
+    /// sampler to follow the caller, pass the parent's `traceparent` in the `otel.parent` field instead:
+    /// ```rust
+    /// # let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+    /// let span = tracing::info_span!("handle request", otel.parent = traceparent);
+    /// ```
+    fn set_parent(&self, parent: SpanContext);
+
+    /// Record `error` in an `exception` event, with its type name in `exception.type`, and fail
+    /// this span if its status is unset.
+    ///
+    /// Errors recorded as fields, like `tracing::error!(error = &error as &dyn Error)`, only get an
+    /// `exception.type` when they are one of the standard library's errors, because a `dyn Error`
+    /// does not know its type name.
+    /// ```rust
+    /// use tracing_actions::ActionSpanExt;
+    ///
+    /// let span = tracing::info_span!("read config");
+    /// if let Err(error) = "x".parse::<u16>() {
+    ///     span.record_exception(&error);
+    /// }
+    /// ```
+    fn record_exception<E: Error + 'static>(&self, error: &E);
+}
+
+impl ActionSpanExt for tracing::Span {
+    fn add_link(&self, link: ActionLink) {
+        with_action_span(self, |action_span| action_span.add_link(link));
+    }
+
+    fn span_context(&self) -> Option<SpanContext> {
+        with_action_span(self, |action_span| action_span.span_context())
+    }
+
+    fn set_parent(&self, parent: SpanContext) {
+        if !parent.is_valid() {
+            log::debug!("remote parent is not valid - not setting it");
+            return;
+        }
+        self.with_subscriber(|(id, dispatch)| {
+            if let Some(access) = dispatch.downcast_ref::<WithActionSpan>() {
+                (access.set_parent)(dispatch, id, &parent)
+            }
+        });
+    }
+
+    fn record_exception<E: Error + 'static>(&self, error: &E) {
+        self.with_subscriber(|(id, dispatch)| {
+            if let Some(access) = dispatch.downcast_ref::<WithActionSpan>() {
+                let now = (access.now)(dispatch);
+                (access.access)(dispatch, id, &mut |action_span| {
+                    action_span.record_exception(error, Some(type_name::<E>()), now)
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use crate::AttributeValue;
+
+/// Caps on how much a span can hold, so a span in a hot loop can't grow without bound.
+///
+/// Whatever goes over a limit is dropped and counted on the span or event, and the
+/// counts are sent along with it. The defaults match opentelemetry's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanLimits {
+    pub max_attributes_per_span: usize,
+    pub max_events_per_span: usize,
+    pub max_links_per_span: usize,
+    pub max_attributes_per_event: usize,
+    pub max_attributes_per_link: usize,
+    /// Longer string values are truncated to this many characters.
+    pub max_attribute_value_length: usize,
+}
+
+impl Default for SpanLimits {
+    fn default() -> Self {
+        Self {
+            max_attributes_per_span: 128,
+            max_events_per_span: 128,
+            max_links_per_span: 128,
+            max_attributes_per_event: 128,
+            max_attributes_per_link: 128,
+            max_attribute_value_length: usize::MAX,
+        }
+    }
+}
+
+impl SpanLimits {
+    /// Replacing an attribute that is already there always works. New attributes past
+    /// `max_attributes` are dropped and counted.
+    pub(crate) fn insert_attribute(
+        &self,
+        max_attributes: usize,
+        attributes: &mut HashMap<&'static str, AttributeValue>,
+        dropped: &mut u32,
+        key: &'static str,
+        mut value: AttributeValue,
+    ) {
+        if max_attributes <= attributes.len() && !attributes.contains_key(key) {
+            *dropped += 1;
+            return;
+        }
+        value.truncate(self.max_attribute_value_length);
+        attributes.insert(key, value);
+    }
+
+    /// Apply the limits to attributes that were collected without them, like a link's.
+    pub(crate) fn limit_attributes(
+        &self,
+        max_attributes: usize,
+        attributes: &mut HashMap<&'static str, AttributeValue>,
+        dropped: &mut u32,
+    ) {
+        for (key, value) in std::mem::take(attributes) {
+            self.insert_attribute(max_attributes, attributes, dropped, key, value);
+        }
+    }
+}
+
+impl AttributeValue {
+    fn truncate(&mut self, max_length: usize) {
+        if let AttributeValue::String(s) | AttributeValue::Error(s) = self {
+            // A string has at most as many chars as bytes, so short ones need no walk.
+            if s.len() <= max_length {
+                return;
+            }
+            if let Some((end, _)) = s.char_indices().nth(max_length) {
+                s.truncate(end);
+            }
+        }
+    }
+}
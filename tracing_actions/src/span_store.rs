@@ -0,0 +1,209 @@
+use std::{
+    collections::{BinaryHeap, HashMap},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+use tracing::span;
+
+use crate::ActionSpan;
+
+/// Live ActionSpans, spread over independently locked shards.
+///
+/// Span ids come from an incrementing counter, so consecutive spans land on different
+/// shards and threads working on different spans rarely wait on each other.
+pub(crate) struct SpanStore {
+    shards: Box<[Shard]>,
+    shard_mask: u64,
+}
+
+// Keep each shard's lock and count on their own cache line, so neighboring shards don't contend
+// either.
+#[repr(align(128))]
+#[derive(Default)]
+struct Shard {
+    spans: Mutex<HashMap<span::Id, ActionSpan>>,
+    /// `spans.len()`, updated with `spans` locked so it can be read without the lock.
+    live_count: AtomicUsize,
+    /// Handles to spans that are not stored but hold a filter scope: unsampled and evicted spans.
+    detached_refs: Mutex<HashMap<span::Id, usize>>,
+}
+
+impl SpanStore {
+    /// `shard_count` is rounded up to a power of 2.
+    pub fn new(shard_count: usize) -> Self {
+        let shard_count = shard_count.max(1).next_power_of_two();
+        Self {
+            shards: (0..shard_count).map(|_| Shard::default()).collect(),
+            shard_mask: shard_count as u64 - 1,
+        }
+    }
+
+    /// A few shards per core is enough to make collisions uncommon.
+    pub fn default_shard_count() -> usize {
+        std::thread::available_parallelism()
+            .map(|cores| cores.get() * 4)
+            .unwrap_or(16)
+    }
+
+    pub fn insert(&self, id: span::Id, action_span: ActionSpan) {
+        let shard = self.shard_of(&id);
+        if shard.lock().insert(id, action_span).is_none() {
+            shard.live_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// How many spans are in the store.
+    pub fn live_count(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.live_count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn remove(&self, id: &span::Id) -> Option<ActionSpan> {
+        let shard = self.shard_of(id);
+        let removed = shard.lock().remove(id);
+        if removed.is_some() {
+            shard.live_count.fetch_sub(1, Ordering::Relaxed);
+        }
+        removed
+    }
+
+    /// Remove every span that `select` picks. Shards are locked one at a time.
+    pub fn remove_where(
+        &self,
+        mut select: impl FnMut(&ActionSpan) -> bool,
+    ) -> Vec<(span::Id, ActionSpan)> {
+        let mut removed = Vec::new();
+        for shard in self.shards.iter() {
+            let mut spans = shard.lock();
+            let selected: Vec<span::Id> = spans
+                .iter()
+                .filter(|(_, span)| select(span))
+                .map(|(id, _)| id.clone())
+                .collect();
+            for id in selected {
+                if let Some(span) = spans.remove(&id) {
+                    shard.live_count.fetch_sub(1, Ordering::Relaxed);
+                    removed.push((id, span));
+                }
+            }
+        }
+        removed
+    }
+
+    /// The ids of up to `count` spans that started first, oldest first.
+    ///
+    /// Keeps the `count` oldest in a heap instead of sorting every live span.
+    pub fn oldest(&self, count: usize) -> Vec<span::Id> {
+        if count == 0 {
+            return Vec::new();
+        }
+        // The youngest of the oldest spans so far is on top, to be replaced by anything older.
+        let mut oldest: BinaryHeap<(SystemTime, u64)> = BinaryHeap::with_capacity(count + 1);
+        for shard in self.shards.iter() {
+            for (id, span) in shard.lock().iter() {
+                if oldest.len() < count {
+                    oldest.push((span.start, id.into_u64()));
+                } else if oldest
+                    .peek()
+                    .is_some_and(|(youngest, _)| span.start < *youngest)
+                {
+                    oldest.pop();
+                    oldest.push((span.start, id.into_u64()));
+                }
+            }
+        }
+        oldest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(_, id)| span::Id::from_u64(id))
+            .collect()
+    }
+
+    pub fn use_span<T>(
+        &self,
+        id: &span::Id,
+        use_it: impl FnOnce(&mut ActionSpan) -> T,
+    ) -> Option<T> {
+        self.shard(id).get_mut(id).map(use_it)
+    }
+
+    pub fn possibly_remove(
+        &self,
+        id: &span::Id,
+        use_it: impl FnOnce(&mut ActionSpan) -> bool,
+    ) -> Option<ActionSpan> {
+        let shard = self.shard_of(id);
+        let mut spans = shard.lock();
+        match spans.get_mut(id).map(use_it) {
+            Some(true) => {
+                let removed = spans.remove(id);
+                if removed.is_some() {
+                    shard.live_count.fetch_sub(1, Ordering::Relaxed);
+                }
+                removed
+            }
+            Some(false) | None => None,
+        }
+    }
+
+    /// Count the handles to a span that is not stored, until the last one closes.
+    pub fn detach(&self, id: span::Id, refs: usize) {
+        self.shard_of(&id).lock_detached().insert(id, refs);
+    }
+
+    pub fn clone_detached(&self, id: &span::Id) {
+        if let Some(refs) = self.shard_of(id).lock_detached().get_mut(id) {
+            *refs += 1;
+        }
+    }
+
+    /// True when the last handle to a detached span was closed.
+    pub fn close_detached(&self, id: &span::Id) -> bool {
+        let mut detached_refs = self.shard_of(id).lock_detached();
+        match detached_refs.get_mut(id) {
+            Some(1) => {
+                detached_refs.remove(id);
+                true
+            }
+            Some(refs) => {
+                *refs -= 1;
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn shard(&self, id: &span::Id) -> std::sync::MutexGuard<'_, HashMap<span::Id, ActionSpan>> {
+        self.shard_of(id).lock()
+    }
+
+    fn shard_of(&self, id: &span::Id) -> &Shard {
+        &self.shards[(id.into_u64() & self.shard_mask) as usize]
+    }
+}
+
+impl Shard {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<span::Id, ActionSpan>> {
+        self.spans
+            .lock()
+            .expect("trace mutex should not be poisoned")
+    }
+
+    fn lock_detached(&self) -> std::sync::MutexGuard<'_, HashMap<span::Id, usize>> {
+        self.detached_refs
+            .lock()
+            .expect("detached span mutex should not be poisoned")
+    }
+}
+
+impl Default for SpanStore {
+    fn default() -> Self {
+        Self::new(Self::default_shard_count())
+    }
+}
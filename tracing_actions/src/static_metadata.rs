@@ -0,0 +1,56 @@
+use tracing::{callsite::Callsite, subscriber::Interest, Level, Metadata};
+use tracing_core::metadata::Kind;
+
+/// A callsite for metadata that tracing-actions makes up itself, rather than one that
+/// came from a `tracing` macro. These are never registered or dispatched.
+pub(crate) struct StaticCallsite(&'static Metadata<'static>);
+
+impl Callsite for StaticCallsite {
+    fn set_interest(&self, _interest: Interest) {}
+
+    fn metadata(&self) -> &Metadata<'_> {
+        self.0
+    }
+}
+
+macro_rules! static_metadata {
+    ($(#[$doc:meta])* $metadata:ident, $callsite:ident, $name:literal, $level:expr, $kind:expr) => {
+        static $callsite: StaticCallsite = StaticCallsite(&$metadata);
+        $(#[$doc])*
+        pub(crate) static $metadata: Metadata<'static> = tracing_core::metadata! {
+            name: $name,
+            target: "tracing_actions",
+            level: $level,
+            fields: &[],
+            callsite: &$callsite,
+            kind: $kind,
+        };
+    };
+}
+
+static_metadata!(
+    /// Stands in for spans that were not sampled, which are not stored anywhere.
+    UNSAMPLED_SPAN,
+    UNSAMPLED_SPAN_CALLSITE,
+    "unsampled",
+    Level::TRACE,
+    Kind::SPAN
+);
+
+static_metadata!(
+    /// Names the events that record errors, following opentelemetry's exception conventions.
+    EXCEPTION_EVENT,
+    EXCEPTION_EVENT_CALLSITE,
+    "exception",
+    Level::ERROR,
+    Kind::EVENT
+);
+
+static_metadata!(
+    /// Names the events that warn about a span that stayed entered for too long at once.
+    SLOW_POLL_EVENT,
+    SLOW_POLL_EVENT_CALLSITE,
+    "slow poll",
+    Level::WARN,
+    Kind::EVENT
+);
@@ -0,0 +1,117 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use thread_local::ThreadLocal;
+
+/// What the subscriber has been doing since it was made. See `ActionTraceSubscriber::stats()`.
+///
+/// Every created span is counted once, in `spans_closed`, `spans_sampled_out`, `spans_evicted`
+/// or `live_spans`, so they add up to `spans_created`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubscriberStats {
+    /// Every new span, sampled or not.
+    pub spans_created: u64,
+    /// Sampled spans that were closed normally and sent to the sink.
+    pub spans_closed: u64,
+    /// Spans the sampler dropped. Most are dropped when they start. A span whose decision
+    /// changed with `set_parent()` is counted when it closes.
+    pub spans_sampled_out: u64,
+    /// Spans that were force-closed for being too old, or for too many open spans.
+    pub spans_evicted: u64,
+    /// Events that went over a span's event limit, or had no recorded span and no orphan event sink.
+    pub events_dropped: u64,
+    /// Spans open right now.
+    pub live_spans: u64,
+    pub span_cache: SpanCacheStats,
+    /// Whatever the sink counts, named by the sink.
+    pub sink: Vec<(&'static str, u64)>,
+}
+
+/// How well a `SpanConstructor` is reusing spans.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpanCacheStats {
+    /// New spans that were reused from the cache.
+    pub hits: u64,
+    /// New spans that were allocated because the cache was empty or busy.
+    pub misses: u64,
+    /// Returned spans that were dropped because the cache was full.
+    pub rejected_full: u64,
+    /// Returned spans that were dropped because another thread held the cache.
+    pub rejected_contended: u64,
+}
+
+/// Counters that each thread adds to on its own cache line, so counting never contends.
+/// Reading a counter sums every thread's count.
+pub(crate) struct PerThreadCounters<T: Send> {
+    threads: ThreadLocal<CachePadded<T>>,
+}
+
+#[repr(align(128))]
+#[derive(Default)]
+struct CachePadded<T>(T);
+
+impl<T: Default + Send + Sync> PerThreadCounters<T> {
+    pub fn count(&self, counter: impl FnOnce(&T) -> &AtomicU64) {
+        counter(&self.threads.get_or_default().0).fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn sum(&self, counter: impl Fn(&T) -> &AtomicU64) -> u64 {
+        self.threads
+            .iter()
+            .map(|counters| counter(&counters.0).load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+impl<T: Send> Default for PerThreadCounters<T> {
+    fn default() -> Self {
+        Self {
+            threads: ThreadLocal::new(),
+        }
+    }
+}
+
+/// The subscriber's own counters, behind `SubscriberStats`.
+pub(crate) type SubscriberCounters = PerThreadCounters<SubscriberCounts>;
+
+#[derive(Default)]
+pub(crate) struct SubscriberCounts {
+    pub spans_created: AtomicU64,
+    pub spans_closed: AtomicU64,
+    pub spans_sampled_out: AtomicU64,
+    pub spans_evicted: AtomicU64,
+    pub events_dropped: AtomicU64,
+}
+
+impl SubscriberCounters {
+    /// Everything but what the subscriber learns from its store, span constructor and sink.
+    pub fn stats(&self) -> SubscriberStats {
+        SubscriberStats {
+            spans_created: self.sum(|counts| &counts.spans_created),
+            spans_closed: self.sum(|counts| &counts.spans_closed),
+            spans_sampled_out: self.sum(|counts| &counts.spans_sampled_out),
+            spans_evicted: self.sum(|counts| &counts.spans_evicted),
+            events_dropped: self.sum(|counts| &counts.events_dropped),
+            ..Default::default()
+        }
+    }
+}
+
+/// `LazySpanCache`'s counters, behind `SpanCacheStats`.
+#[derive(Default)]
+pub(crate) struct SpanCacheCounts {
+    pub hits: AtomicU64,
+    pub misses: AtomicU64,
+    pub rejected_full: AtomicU64,
+    pub rejected_contended: AtomicU64,
+}
+
+impl PerThreadCounters<SpanCacheCounts> {
+    pub fn stats(&self) -> SpanCacheStats {
+        SpanCacheStats {
+            hits: self.sum(|counts| &counts.hits),
+            misses: self.sum(|counts| &counts.misses),
+            rejected_full: self.sum(|counts| &counts.rejected_full),
+            rejected_contended: self.sum(|counts| &counts.rejected_contended),
+        }
+    }
+}
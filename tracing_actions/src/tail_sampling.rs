@@ -0,0 +1,728 @@
+//! Decide which traces to keep after they have finished.
+//!
+//! Head sampling has to decide before anything has happened. A `TailSamplingSink` holds
+//! on to a trace's spans until its root span closes, so it can keep every trace with an
+//! error, or every slow trace, and only a few of the rest.
+//! ```rust
+//! use std::time::Duration;
+//! use tracing_actions::tail_sampling::{
+//!     KeepErrors, KeepFraction, KeepSlow, RateLimited, TailSamplingSink,
+//! };
+//!
+//! struct NoSink;
+//! impl tracing_actions::TraceSink for NoSink {
+//!     fn sink_trace(&self, _trace: &mut tracing_actions::ActionSpan) {}
+//! }
+//!
+//! let sink = TailSamplingSink::new(NoSink)
+//!     .with_policy(KeepErrors)
+//!     .with_policy(KeepSlow::new(Duration::from_millis(500)))
+//!     // 1% of the rest, but never more than 10 per second
+//!     .with_policy(RateLimited::new(KeepFraction::new(0.01), 10));
+//! ```
+
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, VecDeque},
+    mem,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    clock::{Clock, MonotonicClock},
+    sampler::{ratio_threshold, trace_id_is_below},
+    ActionSpan, AttributeValue, SpanStatus, TraceSink,
+};
+
+/// The outcome of a `TailPolicy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TailDecision {
+    Keep,
+    Drop,
+    /// Let the next policy decide.
+    Abstain,
+}
+
+/// One rule in a `TailSamplingSink`'s policy chain.
+///
+/// The trace might be incomplete if it was decided because of a timeout or the buffer limit.
+pub trait TailPolicy {
+    fn evaluate(&self, trace: &[ActionSpan]) -> TailDecision;
+}
+
+/// Keeps traces with at least 1 span in error status.
+pub struct KeepErrors;
+impl TailPolicy for KeepErrors {
+    fn evaluate(&self, trace: &[ActionSpan]) -> TailDecision {
+        if trace
+            .iter()
+            .any(|span| matches!(span.status, SpanStatus::Error))
+        {
+            TailDecision::Keep
+        } else {
+            TailDecision::Abstain
+        }
+    }
+}
+
+/// Keeps traces that took at least `threshold`, from the earliest span start to the latest span end.
+pub struct KeepSlow {
+    threshold: Duration,
+}
+impl KeepSlow {
+    pub fn new(threshold: Duration) -> Self {
+        Self { threshold }
+    }
+}
+impl TailPolicy for KeepSlow {
+    fn evaluate(&self, trace: &[ActionSpan]) -> TailDecision {
+        let start = trace.iter().map(|span| span.start).min();
+        let end = trace.iter().map(|span| span.end).max();
+        let duration = match (start, end) {
+            (Some(start), Some(end)) => end.duration_since(start).unwrap_or_default(),
+            _ => Duration::ZERO,
+        };
+        if self.threshold <= duration {
+            TailDecision::Keep
+        } else {
+            TailDecision::Abstain
+        }
+    }
+}
+
+/// Keeps traces with a span that has the attribute `key`, with a value that looks like `value`.
+///
+/// Numbers and booleans are compared by their string form, so `"404"` matches `404_u64`.
+pub struct KeepMatchingAttribute {
+    key: String,
+    value: String,
+}
+impl KeepMatchingAttribute {
+    pub fn new(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+impl TailPolicy for KeepMatchingAttribute {
+    fn evaluate(&self, trace: &[ActionSpan]) -> TailDecision {
+        let matched = trace.iter().any(|span| {
+            span.attributes
+                .get(self.key.as_str())
+                .map(|value| attribute_matches(value, &self.value))
+                .unwrap_or_default()
+        });
+        if matched {
+            TailDecision::Keep
+        } else {
+            TailDecision::Abstain
+        }
+    }
+}
+
+fn attribute_matches(value: &AttributeValue, expected: &str) -> bool {
+    match value {
+        AttributeValue::String(s) | AttributeValue::Error(s) => s == expected,
+        AttributeValue::F64(f) => f.to_string() == expected,
+        AttributeValue::I64(i) => i.to_string() == expected,
+        AttributeValue::U64(u) => u.to_string() == expected,
+        AttributeValue::I128(i) => i.to_string() == expected,
+        AttributeValue::U128(u) => u.to_string() == expected,
+        AttributeValue::Bool(b) => b.to_string() == expected,
+    }
+}
+
+/// Keeps a fraction of traces, chosen by trace id like `sampler::TraceIdRatio`.
+pub struct KeepFraction {
+    threshold: u64,
+}
+impl KeepFraction {
+    /// `ratio` is clamped to 0..=1.
+    pub fn new(ratio: f64) -> Self {
+        Self {
+            threshold: ratio_threshold(ratio),
+        }
+    }
+}
+impl TailPolicy for KeepFraction {
+    fn evaluate(&self, trace: &[ActionSpan]) -> TailDecision {
+        match trace.first() {
+            Some(span) if trace_id_is_below(&span.trace_id, self.threshold) => TailDecision::Keep,
+            _ => TailDecision::Abstain,
+        }
+    }
+}
+
+/// Limits how many traces per second another policy may keep.
+///
+/// Once `per_second` traces were kept in the current second, traces the inner policy
+/// wants to keep are dropped instead.
+pub struct RateLimited<Policy> {
+    policy: Policy,
+    per_second: u32,
+    clock: Box<dyn Clock + Send + Sync>,
+    window: Mutex<Option<(SystemTime, u32)>>,
+}
+impl<Policy: TailPolicy> RateLimited<Policy> {
+    pub fn new(policy: Policy, per_second: u32) -> Self {
+        Self {
+            policy,
+            per_second,
+            clock: Box::new(MonotonicClock::default()),
+            window: Mutex::new(None),
+        }
+    }
+
+    /// Measure the seconds with `clock`. By default this is a `MonotonicClock`.
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+}
+impl<Policy: TailPolicy> TailPolicy for RateLimited<Policy> {
+    fn evaluate(&self, trace: &[ActionSpan]) -> TailDecision {
+        match self.policy.evaluate(trace) {
+            TailDecision::Keep => {
+                let mut window = self.window.lock().expect("lock should not be poisoned");
+                let now = self.clock.now();
+                let window = match &mut *window {
+                    Some(window) if elapsed(window.0, now) < Duration::from_secs(1) => window,
+                    window => window.insert((now, 0)),
+                };
+                if window.1 < self.per_second {
+                    window.1 += 1;
+                    TailDecision::Keep
+                } else {
+                    TailDecision::Drop
+                }
+            }
+            decision => decision,
+        }
+    }
+}
+
+/// A `TraceSink` that buffers spans by trace, and forwards the traces its policies keep to `inner`.
+///
+/// A trace is decided when its root span closes, when it has waited longer than the timeout, or
+/// when the buffer is full and it is the oldest trace. Policies run in order, and the first one
+/// that does not abstain decides. If they all abstain, the trace is dropped.
+///
+/// Policies run with the buffer locked, so spans that close after their trace was decided, or
+/// while it is being decided, follow that decision.
+/// Like the other sinks, there is no background timer: timeouts are checked as spans arrive.
+/// Call `flush_expired()` periodically if your traffic is bursty, and `flush()` at shutdown.
+pub struct TailSamplingSink<Inner> {
+    inner: Inner,
+    policies: Vec<Box<dyn TailPolicy + Send + Sync>>,
+    timeout: Duration,
+    max_buffered_spans: usize,
+    max_decided_traces: usize,
+    clock: Box<dyn Clock + Send + Sync>,
+    buffer: Mutex<TraceBuffer>,
+}
+
+#[derive(Default)]
+struct TraceBuffer {
+    pending: HashMap<[u8; 16], PendingTrace>,
+    /// Pending traces by when they were first seen, oldest first. The counter breaks ties.
+    pending_by_age: BTreeMap<(SystemTime, u64), [u8; 16]>,
+    next_pending: u64,
+    /// Recent decisions, for spans that close after their root.
+    decided: HashMap<[u8; 16], (bool, SystemTime)>,
+    /// The order decisions were made in, oldest first, to forget them by age and by count.
+    decided_order: VecDeque<([u8; 16], SystemTime)>,
+    buffered_spans: usize,
+    last_sweep: Option<SystemTime>,
+    decided_traces: u64,
+    dropped_traces: u64,
+}
+
+struct PendingTrace {
+    spans: Vec<ActionSpan>,
+    /// This trace's key in `pending_by_age`.
+    age_key: (SystemTime, u64),
+}
+
+impl TraceBuffer {
+    fn add_pending(&mut self, span: ActionSpan, now: SystemTime) {
+        let trace_id = span.trace_id;
+        let pending = match self.pending.entry(trace_id) {
+            Entry::Occupied(pending) => pending.into_mut(),
+            Entry::Vacant(vacant) => {
+                let age_key = (now, self.next_pending);
+                self.next_pending += 1;
+                self.pending_by_age.insert(age_key, trace_id);
+                vacant.insert(PendingTrace {
+                    spans: Vec::new(),
+                    age_key,
+                })
+            }
+        };
+        pending.spans.push(span);
+        self.buffered_spans += 1;
+    }
+
+    fn take_pending(&mut self, trace_id: &[u8; 16]) -> Option<Vec<ActionSpan>> {
+        let pending = self.pending.remove(trace_id)?;
+        self.pending_by_age.remove(&pending.age_key);
+        self.buffered_spans -= pending.spans.len();
+        Some(pending.spans)
+    }
+
+    fn take_oldest(&mut self) -> Option<Vec<ActionSpan>> {
+        let (_, trace_id) = self.pending_by_age.pop_first()?;
+        let pending = self.pending.remove(&trace_id)?;
+        self.buffered_spans -= pending.spans.len();
+        Some(pending.spans)
+    }
+
+    fn remember_decision(&mut self, trace_id: [u8; 16], keep: bool, now: SystemTime, max: usize) {
+        self.decided_traces += 1;
+        if !keep {
+            self.dropped_traces += 1;
+        }
+        self.decided.insert(trace_id, (keep, now));
+        self.decided_order.push_back((trace_id, now));
+        while max < self.decided_order.len() {
+            self.forget_oldest_decision();
+        }
+    }
+
+    fn forget_oldest_decision(&mut self) {
+        if let Some((trace_id, decided_at)) = self.decided_order.pop_front() {
+            // A trace can be decided again after its decision was forgotten; keep the newer one.
+            if self.decided.get(&trace_id).map(|(_, at)| *at) == Some(decided_at) {
+                self.decided.remove(&trace_id);
+            }
+        }
+    }
+}
+
+impl<Inner: TraceSink> TailSamplingSink<Inner> {
+    /// Without policies, every trace is dropped. Add some with `with_policy()`.
+    ///
+    /// By default traces wait up to 30 seconds for their root span, at most 65536
+    /// spans are buffered, and the decisions for the last 65536 traces are remembered.
+    pub fn new(inner: Inner) -> Self {
+        Self {
+            inner,
+            policies: Vec::new(),
+            timeout: Duration::from_secs(30),
+            max_buffered_spans: 65536,
+            max_decided_traces: 65536,
+            clock: Box::new(MonotonicClock::default()),
+            buffer: Default::default(),
+        }
+    }
+
+    /// Add a policy to the end of the chain.
+    pub fn with_policy(mut self, policy: impl TailPolicy + Send + Sync + 'static) -> Self {
+        self.policies.push(Box::new(policy));
+        self
+    }
+
+    /// How long to wait for a trace's root span before deciding without it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The hard limit on buffered spans. The oldest traces are decided early to stay under it.
+    pub fn with_max_buffered_spans(mut self, max_buffered_spans: usize) -> Self {
+        self.max_buffered_spans = max_buffered_spans.max(1);
+        self
+    }
+
+    /// How many decided traces to remember, so spans that close after their root follow the
+    /// decision. Decisions are also forgotten after the timeout. Spans of a forgotten trace
+    /// start a new pending trace.
+    pub fn with_max_decided_traces(mut self, max_decided_traces: usize) -> Self {
+        self.max_decided_traces = max_decided_traces;
+        self
+    }
+
+    /// Measure the timeout with `clock`. By default this is a `MonotonicClock`, so stepping
+    /// the system clock does not expire every buffered trace at once.
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Decide the traces that have waited longer than the timeout.
+    pub fn flush_expired(&self) {
+        let now = self.clock.now();
+        let kept = {
+            let mut buffer = self.buffer.lock().expect("lock should not be poisoned");
+            let expired = self.take_expired(&mut buffer, now);
+            self.decide_all(&mut buffer, expired, now)
+        };
+        self.forward(kept);
+    }
+
+    /// Decide every buffered trace, complete or not.
+    pub fn flush(&self) {
+        let now = self.clock.now();
+        let kept = {
+            let mut buffer = self.buffer.lock().expect("lock should not be poisoned");
+            buffer.buffered_spans = 0;
+            buffer.pending_by_age.clear();
+            let everything: Vec<Vec<ActionSpan>> = buffer
+                .pending
+                .drain()
+                .map(|(_, pending)| pending.spans)
+                .collect();
+            self.decide_all(&mut buffer, everything, now)
+        };
+        self.forward(kept);
+    }
+
+    fn take_expired(&self, buffer: &mut TraceBuffer, now: SystemTime) -> Vec<Vec<ActionSpan>> {
+        buffer.last_sweep = Some(now);
+        while let Some((_, decided_at)) = buffer.decided_order.front() {
+            if elapsed(*decided_at, now) < self.timeout {
+                break;
+            }
+            buffer.forget_oldest_decision();
+        }
+        let mut expired = Vec::new();
+        while let Some((&(first_seen, _), _)) = buffer.pending_by_age.first_key_value() {
+            if elapsed(first_seen, now) < self.timeout {
+                break;
+            }
+            expired.extend(buffer.take_oldest());
+        }
+        expired
+    }
+
+    /// Returns the traces to keep, to forward once the buffer is unlocked.
+    fn decide_all(
+        &self,
+        buffer: &mut TraceBuffer,
+        traces: Vec<Vec<ActionSpan>>,
+        now: SystemTime,
+    ) -> Vec<Vec<ActionSpan>> {
+        traces
+            .into_iter()
+            .filter(|trace| self.decide(buffer, trace, now))
+            .collect()
+    }
+
+    fn decide(&self, buffer: &mut TraceBuffer, trace: &[ActionSpan], now: SystemTime) -> bool {
+        let keep = self
+            .policies
+            .iter()
+            .map(|policy| policy.evaluate(trace))
+            .find(|decision| *decision != TailDecision::Abstain)
+            == Some(TailDecision::Keep);
+        if let Some(span) = trace.first() {
+            buffer.remember_decision(span.trace_id, keep, now, self.max_decided_traces);
+        }
+        keep
+    }
+
+    fn forward(&self, kept: Vec<Vec<ActionSpan>>) {
+        for mut trace in kept {
+            for span in &mut trace {
+                self.inner.sink_trace(span);
+            }
+        }
+    }
+}
+
+/// How long ago `since` was. Zero if the clock went backwards.
+fn elapsed(since: SystemTime, now: SystemTime) -> Duration {
+    now.duration_since(since).unwrap_or_default()
+}
+
+impl<Inner: TraceSink> TraceSink for TailSamplingSink<Inner> {
+    fn sink_trace(&self, trace: &mut ActionSpan) {
+        let mut span = mem::take(trace);
+        let now = self.clock.now();
+        let mut ready = Vec::new();
+        let kept = {
+            let mut buffer = self.buffer.lock().expect("lock should not be poisoned");
+            let decision = buffer
+                .decided
+                .get(&span.trace_id)
+                .filter(|(_, decided_at)| elapsed(*decided_at, now) < self.timeout);
+            if let Some((keep, _)) = decision {
+                let keep = *keep;
+                drop(buffer);
+                if keep {
+                    self.inner.sink_trace(&mut span);
+                }
+                return;
+            }
+
+            let trace_id = span.trace_id;
+            // The local root of a distributed trace has a remote parent.
+            let is_root = span.parent_span_id.is_none() || span.parent_is_remote;
+            buffer.add_pending(span, now);
+
+            if is_root {
+                ready.extend(buffer.take_pending(&trace_id));
+            }
+            let sweep_due = buffer
+                .last_sweep
+                .map(|last_sweep| self.timeout / 8 <= elapsed(last_sweep, now))
+                .unwrap_or(true);
+            if sweep_due {
+                ready.extend(self.take_expired(&mut buffer, now));
+            }
+            while self.max_buffered_spans < buffer.buffered_spans {
+                match buffer.take_oldest() {
+                    Some(oldest) => ready.push(oldest),
+                    None => break,
+                }
+            }
+            self.decide_all(&mut buffer, ready, now)
+        };
+        self.forward(kept);
+    }
+
+    /// The inner sink's counters, and how many spans are buffered and traces were decided
+    /// and dropped.
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        let mut stats = self.inner.stats();
+        let buffer = self.buffer.lock().expect("lock should not be poisoned");
+        stats.extend([
+            ("tail_sampling_buffered_spans", buffer.buffered_spans as u64),
+            ("tail_sampling_decided_traces", buffer.decided_traces),
+            ("tail_sampling_dropped_traces", buffer.dropped_traces),
+        ]);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use crate::{
+        clock::MockClock,
+        test_support::{Spans, TestSink},
+        ActionSpan, AttributeValue, SpanStatus, TraceSink,
+    };
+
+    use super::{
+        KeepErrors, KeepFraction, KeepMatchingAttribute, KeepSlow, RateLimited, TailSamplingSink,
+    };
+
+    fn span(trace: u8, span: u8, parent: Option<u8>, duration: Duration) -> ActionSpan {
+        let start = SystemTime::UNIX_EPOCH;
+        ActionSpan {
+            trace_id: [trace; 16],
+            span_id: [span; 8],
+            parent_span_id: parent.map(|parent| [parent; 8]),
+            start,
+            end: start + duration,
+            ..Default::default()
+        }
+    }
+
+    fn kept_traces(spans: &Spans) -> Vec<[u8; 16]> {
+        spans
+            .lock()
+            .expect("local mutex")
+            .iter()
+            .map(|span| span.trace_id)
+            .collect()
+    }
+
+    #[test]
+    fn policies() {
+        let spans: Spans = Default::default();
+        let sink = TailSamplingSink::new(TestSink {
+            spans: spans.clone(),
+        })
+        .with_policy(KeepErrors)
+        .with_policy(KeepSlow::new(Duration::from_secs(1)));
+
+        // A fast, ok trace is dropped
+        sink.sink_trace(&mut span(1, 2, Some(1), Duration::from_millis(1)));
+        sink.sink_trace(&mut span(1, 1, None, Duration::from_millis(2)));
+        // A trace with an error is kept, including the span that closes late
+        let mut error = span(2, 2, Some(1), Duration::from_millis(1));
+        error.status = SpanStatus::Error;
+        sink.sink_trace(&mut error);
+        sink.sink_trace(&mut span(2, 1, None, Duration::from_millis(2)));
+        sink.sink_trace(&mut span(2, 3, Some(1), Duration::from_millis(1)));
+        // A slow trace is kept
+        sink.sink_trace(&mut span(3, 1, None, Duration::from_secs(2)));
+
+        let kept: Vec<([u8; 16], [u8; 8])> = spans
+            .lock()
+            .expect("local mutex")
+            .iter()
+            .map(|span| (span.trace_id, span.span_id))
+            .collect();
+        assert_eq!(
+            vec![
+                ([2; 16], [2; 8]),
+                ([2; 16], [1; 8]),
+                ([2; 16], [3; 8]),
+                ([3; 16], [1; 8]),
+            ],
+            kept
+        );
+        assert!(sink.stats().contains(&("tail_sampling_dropped_traces", 1)));
+    }
+
+    #[test]
+    fn buffer_limit() {
+        let spans: Spans = Default::default();
+        let sink = TailSamplingSink::new(TestSink {
+            spans: spans.clone(),
+        })
+        .with_policy(KeepSlow::new(Duration::ZERO))
+        .with_max_buffered_spans(2);
+
+        sink.sink_trace(&mut span(1, 2, Some(1), Duration::from_millis(1)));
+        sink.sink_trace(&mut span(2, 2, Some(1), Duration::from_millis(1)));
+        assert_eq!(0, spans.lock().expect("local mutex").len());
+
+        // The oldest incomplete trace is decided to make room
+        sink.sink_trace(&mut span(3, 2, Some(1), Duration::from_millis(1)));
+        assert_eq!([1; 16], spans.lock().expect("local mutex")[0].trace_id);
+
+        assert_eq!(
+            vec![
+                ("tail_sampling_buffered_spans", 2),
+                ("tail_sampling_decided_traces", 1),
+                ("tail_sampling_dropped_traces", 0),
+            ],
+            sink.stats()
+        );
+
+        sink.flush();
+        assert_eq!(3, spans.lock().expect("local mutex").len());
+    }
+
+    #[test]
+    fn decision_limit() {
+        let spans: Spans = Default::default();
+        let sink = TailSamplingSink::new(TestSink {
+            spans: spans.clone(),
+        })
+        .with_policy(KeepSlow::new(Duration::ZERO))
+        .with_max_decided_traces(2);
+
+        for trace in 1..=3 {
+            sink.sink_trace(&mut span(trace, 1, None, Duration::from_millis(1)));
+        }
+        assert_eq!(2, sink.buffer.lock().expect("local mutex").decided.len());
+
+        // Trace 1's decision was forgotten, so its late span waits for a new decision.
+        sink.sink_trace(&mut span(1, 2, Some(1), Duration::from_millis(1)));
+        sink.sink_trace(&mut span(3, 2, Some(1), Duration::from_millis(1)));
+        assert_eq!(4, spans.lock().expect("local mutex").len());
+        sink.flush();
+        assert_eq!(5, spans.lock().expect("local mutex").len());
+    }
+
+    #[test]
+    fn timeout() {
+        let spans: Spans = Default::default();
+        let clock = MockClock::default();
+        let sink = TailSamplingSink::new(TestSink {
+            spans: spans.clone(),
+        })
+        .with_policy(KeepSlow::new(Duration::ZERO))
+        .with_timeout(Duration::from_secs(10))
+        .with_clock(clock.clone());
+
+        sink.sink_trace(&mut span(1, 2, Some(1), Duration::from_millis(1)));
+        clock.advance(Duration::from_secs(5));
+        sink.sink_trace(&mut span(2, 2, Some(1), Duration::from_millis(1)));
+        clock.advance(Duration::from_secs(5));
+        sink.flush_expired();
+        assert_eq!(
+            vec![[1; 16]],
+            kept_traces(&spans),
+            "only trace 1 has waited 10s"
+        );
+
+        // Arriving spans check the timeout too, every timeout / 8.
+        clock.advance(Duration::from_secs(5));
+        sink.sink_trace(&mut span(3, 2, Some(1), Duration::from_millis(1)));
+        assert_eq!(vec![[1; 16], [2; 16]], kept_traces(&spans));
+
+        // A late span follows its trace's decision, until that is forgotten after the timeout.
+        sink.sink_trace(&mut span(1, 3, Some(1), Duration::from_millis(1)));
+        assert_eq!(vec![[1; 16], [2; 16], [1; 16]], kept_traces(&spans));
+        clock.advance(Duration::from_secs(5));
+        sink.sink_trace(&mut span(1, 4, Some(1), Duration::from_millis(1)));
+        assert_eq!(
+            3,
+            kept_traces(&spans).len(),
+            "the late span waits for a new decision"
+        );
+    }
+
+    #[test]
+    fn rate_limited() {
+        let spans: Spans = Default::default();
+        let clock = MockClock::default();
+        let sink = TailSamplingSink::new(TestSink {
+            spans: spans.clone(),
+        })
+        .with_policy(RateLimited::new(KeepSlow::new(Duration::ZERO), 2).with_clock(clock.clone()));
+
+        for trace in 1..=3 {
+            sink.sink_trace(&mut span(trace, 1, None, Duration::from_millis(1)));
+        }
+        assert_eq!(vec![[1; 16], [2; 16]], kept_traces(&spans));
+
+        clock.advance(Duration::from_secs(1));
+        sink.sink_trace(&mut span(4, 1, None, Duration::from_millis(1)));
+        assert_eq!(vec![[1; 16], [2; 16], [4; 16]], kept_traces(&spans));
+    }
+
+    #[test]
+    fn keep_matching_attribute() {
+        let spans: Spans = Default::default();
+        let sink = TailSamplingSink::new(TestSink {
+            spans: spans.clone(),
+        })
+        .with_policy(KeepMatchingAttribute::new("http.status_code", "404"));
+
+        let mut not_found = span(1, 2, Some(1), Duration::from_millis(1));
+        not_found
+            .attributes
+            .insert("http.status_code", AttributeValue::U64(404));
+        sink.sink_trace(&mut not_found);
+        sink.sink_trace(&mut span(1, 1, None, Duration::from_millis(1)));
+        let mut ok = span(2, 1, None, Duration::from_millis(1));
+        ok.attributes
+            .insert("http.status_code", AttributeValue::U64(200));
+        sink.sink_trace(&mut ok);
+        sink.sink_trace(&mut span(3, 1, None, Duration::from_millis(1)));
+
+        assert_eq!(vec![[1; 16], [1; 16]], kept_traces(&spans));
+    }
+
+    #[test]
+    fn keep_fraction() {
+        let spans: Spans = Default::default();
+        let sink = TailSamplingSink::new(TestSink {
+            spans: spans.clone(),
+        })
+        .with_policy(KeepFraction::new(0.5));
+
+        // The random half of a trace id decides: 0x00.. is below half, 0xff.. is not.
+        for trace in [0x00, 0xff, 0x10, 0xf0] {
+            sink.sink_trace(&mut span(trace, 1, None, Duration::from_millis(1)));
+        }
+        assert_eq!(vec![[0x00; 16], [0x10; 16]], kept_traces(&spans));
+
+        let none = TailSamplingSink::new(TestSink::default()).with_policy(KeepFraction::new(0.0));
+        let all = TailSamplingSink::new(TestSink::default()).with_policy(KeepFraction::new(1.0));
+        for trace in [0x00, 0xff] {
+            none.sink_trace(&mut span(trace, 1, None, Duration::from_millis(1)));
+            all.sink_trace(&mut span(trace, 1, None, Duration::from_millis(1)));
+        }
+        assert!(kept_traces(&none.inner.spans).is_empty());
+        assert_eq!(vec![[0x00; 16], [0xff; 16]], kept_traces(&all.inner.spans));
+    }
+}
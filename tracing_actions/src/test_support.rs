@@ -0,0 +1,95 @@
+//! Sinks and subscribers shared by the tests.
+use std::sync::{Arc, Mutex};
+
+use tracing::{
+    field::{Field, Visit},
+    metadata::LevelFilter,
+};
+
+use crate::{
+    sampler::{Sampler, SamplingDecision, SamplingParameters},
+    span_constructor::{LazySpanCache, SpanConstructor},
+    ActionEvent, ActionSpan, ActionTraceSubscriber, EventSink, TraceSink,
+};
+
+/// Every span a `TestSink` was sent, in the order they closed.
+pub(crate) type Spans = Arc<Mutex<Vec<ActionSpan>>>;
+
+/// Keeps a copy of every span.
+#[derive(Default)]
+pub(crate) struct TestSink {
+    pub spans: Spans,
+}
+
+impl TraceSink for TestSink {
+    fn sink_trace(&self, action_span: &mut ActionSpan) {
+        self.spans
+            .lock()
+            .expect("local lock should work")
+            .push(action_span.clone());
+    }
+}
+
+/// Keeps a copy of every orphan event.
+#[derive(Default)]
+pub(crate) struct TestEventSink {
+    pub events: Arc<Mutex<Vec<ActionEvent>>>,
+}
+
+impl EventSink for TestEventSink {
+    fn sink_event(&self, event: &mut ActionEvent) {
+        self.events
+            .lock()
+            .expect("local lock should work")
+            .push(event.clone());
+    }
+}
+
+/// Samples new roots, and spans given a remote parent only when their `user` field is `bob`.
+pub(crate) struct SampleBobsRemoteParents;
+
+impl Sampler for SampleBobsRemoteParents {
+    fn should_sample(&self, parameters: &SamplingParameters<'_>) -> SamplingDecision {
+        struct User(Option<String>);
+        impl Visit for User {
+            fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+
+            fn record_str(&mut self, field: &Field, value: &str) {
+                if field.name() == "user" {
+                    self.0 = Some(value.to_string());
+                }
+            }
+        }
+
+        let mut user = User(None);
+        parameters.attributes.record(&mut user);
+        if parameters.parent.is_none() || user.0.as_deref() == Some("bob") {
+            SamplingDecision::RecordAndSample
+        } else {
+            SamplingDecision::Drop
+        }
+    }
+}
+
+/// A debug level subscriber that sends its spans to `spans`. Configure the rest with its builder.
+pub(crate) fn test_subscriber(spans: &Spans) -> ActionTraceSubscriber<TestSink, LazySpanCache> {
+    test_subscriber_with(spans, LazySpanCache::default())
+}
+
+/// Like `test_subscriber()`, with your own span constructor.
+pub(crate) fn test_subscriber_with<TSpanConstructor: SpanConstructor + 'static>(
+    spans: &Spans,
+    span_constructor: TSpanConstructor,
+) -> ActionTraceSubscriber<TestSink, TSpanConstructor> {
+    static INITIALIZE_LOGGER_ONCE: std::sync::Once = std::sync::Once::new();
+    INITIALIZE_LOGGER_ONCE.call_once(|| {
+        env_logger::builder().is_test(true).init();
+    });
+    ActionTraceSubscriber::new(
+        LevelFilter::DEBUG,
+        TestSink {
+            spans: spans.clone(),
+        },
+        span_constructor,
+    )
+}
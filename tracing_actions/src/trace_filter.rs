@@ -0,0 +1,661 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use arc_swap::ArcSwap;
+use thread_local::ThreadLocal;
+use tracing::{
+    callsite,
+    field::{Field, Visit},
+    metadata::LevelFilter,
+    span,
+    subscriber::Interest,
+    Metadata,
+};
+
+/// Decides which spans and events an `ActionTraceSubscriber` records.
+///
+/// A filter is a comma separated list of directives, like `my_crate::db=trace,hyper=warn,info`.
+/// Each directive looks like `target[span{field=value}]=level`, and every part is optional:
+/// * `info` enables everything at `info` or more severe.
+/// * `my_crate::db=trace` enables everything whose target starts with `my_crate::db` at `trace`.
+///   A target without a level, like `my_crate`, enables every level.
+/// * `[request]=debug` records `request` spans, and enables everything at `debug` within them.
+/// * `my_crate[request{user=bob}]=trace` does the same for `request` spans from `my_crate`, but
+///   only raises the level within the spans that were created with a `user` field of `bob`.
+///   `{user}` matches any span with a `user` field.
+///
+/// When several target directives match a callsite, the one with the longest target wins.
+///
+/// Decisions for targets and span names are made once per callsite when it is registered, so
+/// the only per-span cost is for span directives with field predicates.
+pub struct TraceFilter {
+    statics: Vec<StaticDirective>,
+    dynamics: Vec<DynamicDirective>,
+    callsites: RwLock<HashMap<callsite::Identifier, CallsiteDecision>>,
+    by_id: RwLock<HashMap<span::Id, LevelFilter>>,
+    scope: ThreadLocal<RefCell<Vec<LevelFilter>>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct StaticDirective {
+    target: Option<String>,
+    level: LevelFilter,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DynamicDirective {
+    target: Option<String>,
+    span_name: String,
+    fields: Vec<FieldMatch>,
+    level: LevelFilter,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FieldMatch {
+    name: String,
+    value: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum CallsiteDecision {
+    Always,
+    Never,
+    /// Depends on whether we are inside a span that matched a span directive.
+    InScope,
+    /// A span that may open a scope. The directives are indexes into `dynamics`.
+    OpensScope(Arc<[usize]>),
+}
+
+/// Describes a directive that could not be parsed.
+#[derive(Debug, Clone)]
+pub struct FilterParseError {
+    directive: String,
+    reason: &'static str,
+}
+
+impl Display for FilterParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid filter directive `{}`: {}",
+            self.directive, self.reason
+        )
+    }
+}
+
+impl Error for FilterParseError {}
+
+impl TraceFilter {
+    /// The most verbose level any directive can enable.
+    pub fn max_level(&self) -> LevelFilter {
+        self.statics
+            .iter()
+            .map(|directive| directive.level)
+            .chain(self.dynamics.iter().map(|directive| directive.level))
+            .max()
+            .unwrap_or(LevelFilter::OFF)
+    }
+
+    pub(crate) fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        let decision = self.decide(metadata);
+        let interest = match decision {
+            CallsiteDecision::Always | CallsiteDecision::OpensScope(_) => Interest::always(),
+            CallsiteDecision::Never => Interest::never(),
+            CallsiteDecision::InScope => Interest::sometimes(),
+        };
+        self.callsites
+            .write()
+            .expect("callsite lock should not be poisoned")
+            .insert(metadata.callsite(), decision);
+        interest
+    }
+
+    pub(crate) fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        if self.dynamics.is_empty() {
+            // Without span directives nothing depends on the scope, or needs the callsite cache.
+            return metadata.level() <= &self.static_level(metadata);
+        }
+        let decision = self
+            .callsites
+            .read()
+            .expect("callsite lock should not be poisoned")
+            .get(&metadata.callsite())
+            .cloned();
+        match decision.unwrap_or_else(|| self.decide(metadata)) {
+            CallsiteDecision::Always | CallsiteDecision::OpensScope(_) => true,
+            CallsiteDecision::Never => false,
+            CallsiteDecision::InScope => match self.scope.get() {
+                Some(scope) => scope.borrow().iter().any(|level| metadata.level() <= level),
+                None => false,
+            },
+        }
+    }
+
+    /// Returns true when the span opens a scope, which must be closed with `on_close()`.
+    pub(crate) fn on_new_span(&self, attributes: &span::Attributes<'_>, id: &span::Id) -> bool {
+        if self.dynamics.is_empty() {
+            return false;
+        }
+        let directives = match self
+            .callsites
+            .read()
+            .expect("callsite lock should not be poisoned")
+            .get(&attributes.metadata().callsite())
+        {
+            Some(CallsiteDecision::OpensScope(directives)) => directives.clone(),
+            _ => return false,
+        };
+
+        let mut values = FieldValues::default();
+        attributes.record(&mut values);
+        let level = directives
+            .iter()
+            .map(|index| &self.dynamics[*index])
+            .filter(|directive| directive.fields.iter().all(|field| values.matches(field)))
+            .map(|directive| directive.level)
+            .max();
+        match level {
+            Some(level) => {
+                self.by_id
+                    .write()
+                    .expect("span scope lock should not be poisoned")
+                    .insert(id.clone(), level);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn on_enter(&self, id: &span::Id) {
+        if let Some(level) = self.span_scope(id) {
+            self.scope.get_or_default().borrow_mut().push(level);
+        }
+    }
+
+    pub(crate) fn on_exit(&self, id: &span::Id) {
+        if self.span_scope(id).is_some() {
+            self.scope.get_or_default().borrow_mut().pop();
+        }
+    }
+
+    pub(crate) fn on_close(&self, id: &span::Id) {
+        if self.dynamics.is_empty() {
+            return;
+        }
+        self.by_id
+            .write()
+            .expect("span scope lock should not be poisoned")
+            .remove(id);
+    }
+
+    pub(crate) fn has_scope(&self, id: &span::Id) -> bool {
+        self.span_scope(id).is_some()
+    }
+
+    fn span_scope(&self, id: &span::Id) -> Option<LevelFilter> {
+        if self.dynamics.is_empty() {
+            return None;
+        }
+        self.by_id
+            .read()
+            .expect("span scope lock should not be poisoned")
+            .get(id)
+            .copied()
+    }
+
+    /// The level of the most specific target directive for `metadata`.
+    fn static_level(&self, metadata: &Metadata<'_>) -> LevelFilter {
+        self.statics
+            .iter()
+            .find(|directive| target_matches(&directive.target, metadata.target()))
+            .map(|directive| directive.level)
+            .unwrap_or(LevelFilter::OFF)
+    }
+
+    fn from_directives(mut statics: Vec<StaticDirective>, dynamics: Vec<DynamicDirective>) -> Self {
+        // Most specific first, so the first match wins.
+        statics.sort_by_key(|directive| {
+            std::cmp::Reverse(directive.target.as_ref().map(|t| t.len() + 1).unwrap_or(0))
+        });
+        Self {
+            statics,
+            dynamics,
+            callsites: Default::default(),
+            by_id: Default::default(),
+            scope: ThreadLocal::new(),
+        }
+    }
+
+    fn decide(&self, metadata: &Metadata<'_>) -> CallsiteDecision {
+        if metadata.is_span() {
+            let opens_scope: Vec<usize> = self
+                .dynamics
+                .iter()
+                .enumerate()
+                .filter(|(_, directive)| {
+                    directive.span_name == metadata.name()
+                        && target_matches(&directive.target, metadata.target())
+                })
+                .map(|(index, _)| index)
+                .collect();
+            if !opens_scope.is_empty() {
+                return CallsiteDecision::OpensScope(opens_scope.into());
+            }
+        }
+
+        if metadata.level() <= &self.static_level(metadata) {
+            CallsiteDecision::Always
+        } else if self
+            .dynamics
+            .iter()
+            .any(|directive| metadata.level() <= &directive.level)
+        {
+            CallsiteDecision::InScope
+        } else {
+            CallsiteDecision::Never
+        }
+    }
+}
+
+/// Swaps the filter of a live `ActionTraceSubscriber`.
+///
+/// Get one from `ActionTraceSubscriber::new_reloadable()`. Reloading rebuilds `tracing`'s callsite
+/// interest cache, so the new filter applies to callsites that were already registered. Spans that
+/// are open when you reload keep recording; only new filter decisions use the new filter.
+#[derive(Clone)]
+pub struct FilterReloadHandle {
+    filter: Arc<ArcSwap<TraceFilter>>,
+}
+
+impl FilterReloadHandle {
+    pub(crate) fn new(filter: Arc<ArcSwap<TraceFilter>>) -> Self {
+        Self { filter }
+    }
+
+    /// Replace the subscriber's filter.
+    pub fn reload(&self, filter: TraceFilter) {
+        self.filter.store(Arc::new(filter));
+        callsite::rebuild_interest_cache();
+    }
+
+    /// Replace the subscriber's filter with a single global level.
+    pub fn set_level(&self, level: LevelFilter) {
+        self.reload(level.into())
+    }
+
+    /// Run a function with the current filter, for example to log it.
+    pub fn with_current<T>(&self, f: impl FnOnce(&TraceFilter) -> T) -> T {
+        f(&self.filter.load())
+    }
+}
+
+impl From<LevelFilter> for TraceFilter {
+    fn from(level: LevelFilter) -> Self {
+        Self::from_directives(
+            vec![StaticDirective {
+                target: None,
+                level,
+            }],
+            vec![],
+        )
+    }
+}
+
+impl FromStr for TraceFilter {
+    type Err = FilterParseError;
+
+    fn from_str(directives: &str) -> Result<Self, Self::Err> {
+        let mut statics = Vec::new();
+        let mut dynamics = Vec::new();
+        for directive in split_outside_braces(directives, ',') {
+            let directive = directive.trim();
+            if directive.is_empty() {
+                continue;
+            }
+            match parse_directive(directive)? {
+                Directive::Static(directive) => statics.push(directive),
+                Directive::Dynamic(directive) => dynamics.push(directive),
+            }
+        }
+        Ok(Self::from_directives(statics, dynamics))
+    }
+}
+
+impl std::fmt::Debug for TraceFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TraceFilter")
+            .field("statics", &self.statics)
+            .field("dynamics", &self.dynamics)
+            .finish()
+    }
+}
+
+enum Directive {
+    Static(StaticDirective),
+    Dynamic(DynamicDirective),
+}
+
+fn parse_directive(directive: &str) -> Result<Directive, FilterParseError> {
+    let error = |reason| FilterParseError {
+        directive: directive.to_string(),
+        reason,
+    };
+
+    // The level separator is the first `=` after any span section.
+    let span_end = directive.rfind(']').map(|i| i + 1).unwrap_or(0);
+    let (selector, level) = match directive[span_end..].find('=') {
+        Some(i) => (
+            &directive[..span_end + i],
+            Some(&directive[span_end + i + 1..]),
+        ),
+        None => (directive, None),
+    };
+    let level = match level {
+        Some(level) => Some(
+            level
+                .trim()
+                .parse::<LevelFilter>()
+                .map_err(|_| error("unknown level"))?,
+        ),
+        None => None,
+    };
+
+    let (target, span) = match selector.find('[') {
+        Some(start) => {
+            if !selector.ends_with(']') {
+                return Err(error("span selector is missing `]`"));
+            }
+            (
+                &selector[..start],
+                Some(&selector[start + 1..selector.len() - 1]),
+            )
+        }
+        None => (selector, None),
+    };
+    let target = Some(target.trim())
+        .filter(|target| !target.is_empty())
+        .map(str::to_string);
+
+    match span {
+        None => match (target, level) {
+            (Some(target), None) => match target.parse::<LevelFilter>() {
+                // A bare level applies to every target
+                Ok(level) => Ok(Directive::Static(StaticDirective {
+                    target: None,
+                    level,
+                })),
+                Err(_) => Ok(Directive::Static(StaticDirective {
+                    target: Some(target),
+                    level: LevelFilter::TRACE,
+                })),
+            },
+            (target, Some(level)) => Ok(Directive::Static(StaticDirective { target, level })),
+            (None, None) => Err(error("empty directive")),
+        },
+        Some(span) => {
+            let (span_name, fields) = match span.find('{') {
+                Some(start) => {
+                    if !span.ends_with('}') {
+                        return Err(error("field selector is missing `}`"));
+                    }
+                    (&span[..start], &span[start + 1..span.len() - 1])
+                }
+                None => (span, ""),
+            };
+            let span_name = span_name.trim();
+            if span_name.is_empty() {
+                return Err(error("span selector needs a span name"));
+            }
+            let fields = fields
+                .split(',')
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+                .map(|field| match field.split_once('=') {
+                    Some((name, value)) => FieldMatch {
+                        name: name.trim().to_string(),
+                        value: Some(value.trim().trim_matches('"').to_string()),
+                    },
+                    None => FieldMatch {
+                        name: field.to_string(),
+                        value: None,
+                    },
+                })
+                .collect();
+            Ok(Directive::Dynamic(DynamicDirective {
+                target,
+                span_name: span_name.to_string(),
+                fields,
+                level: level.unwrap_or(LevelFilter::TRACE),
+            }))
+        }
+    }
+}
+
+fn split_outside_braces(s: &str, separator: char) -> impl Iterator<Item = &str> {
+    let mut depth = 0;
+    let mut start = 0;
+    let mut parts = Vec::new();
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+    parts.push(&s[start..]);
+    parts.into_iter()
+}
+
+fn target_matches(directive_target: &Option<String>, target: &str) -> bool {
+    match directive_target {
+        Some(directive_target) => target.starts_with(directive_target.as_str()),
+        None => true,
+    }
+}
+
+/// Collects a span's initial field values as strings, for matching field predicates.
+#[derive(Default)]
+struct FieldValues {
+    values: Vec<(&'static str, String)>,
+}
+
+impl FieldValues {
+    fn matches(&self, field: &FieldMatch) -> bool {
+        self.values.iter().any(|(name, value)| {
+            *name == field.name
+                && match &field.value {
+                    Some(expected) => expected == value,
+                    None => true,
+                }
+        })
+    }
+}
+
+impl Visit for FieldValues {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.values.push((field.name(), format!("{value:?}")));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.values.push((field.name(), value.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tracing::metadata::LevelFilter;
+
+    use crate::{
+        sampler::{Sampler, SamplingDecision, SamplingParameters},
+        span_constructor::AlwaysNewSpanConstructor,
+        test_support::{test_subscriber_with, Spans, TestSink},
+        ActionTraceSubscriber, TraceFilter,
+    };
+
+    use super::{DynamicDirective, FieldMatch, StaticDirective};
+
+    fn span_names(filter: &str, traced: impl FnOnce()) -> Vec<&'static str> {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber_with(&spans, AlwaysNewSpanConstructor)
+            .with_filter(filter.parse().expect("filter should parse"));
+        tracing::subscriber::with_default(subscriber, traced);
+        let spans = spans.lock().expect("local mutex");
+        spans
+            .iter()
+            .map(|span| span.metadata.expect("there is metadata").name())
+            .collect()
+    }
+
+    #[test]
+    fn parse_directives() {
+        let filter: TraceFilter =
+            "my_crate::db=trace,hyper=warn,info,req[handle{user=bob, id}]=debug"
+                .parse()
+                .expect("filter should parse");
+        assert_eq!(
+            vec![
+                StaticDirective {
+                    target: Some("my_crate::db".to_string()),
+                    level: LevelFilter::TRACE,
+                },
+                StaticDirective {
+                    target: Some("hyper".to_string()),
+                    level: LevelFilter::WARN,
+                },
+                StaticDirective {
+                    target: None,
+                    level: LevelFilter::INFO,
+                },
+            ],
+            filter.statics
+        );
+        assert_eq!(
+            vec![DynamicDirective {
+                target: Some("req".to_string()),
+                span_name: "handle".to_string(),
+                fields: vec![
+                    FieldMatch {
+                        name: "user".to_string(),
+                        value: Some("bob".to_string()),
+                    },
+                    FieldMatch {
+                        name: "id".to_string(),
+                        value: None,
+                    },
+                ],
+                level: LevelFilter::DEBUG,
+            }],
+            filter.dynamics
+        );
+        assert_eq!(LevelFilter::TRACE, filter.max_level());
+
+        assert!("hyper=loud".parse::<TraceFilter>().is_err());
+        assert!("[span{a=1".parse::<TraceFilter>().is_err());
+    }
+
+    #[test]
+    fn target_directives() {
+        let names = span_names("tracing_actions::trace_filter=debug,warn", || {
+            tracing::debug_span!("by target").in_scope(|| {});
+            tracing::debug_span!(target: "elsewhere", "filtered").in_scope(|| {});
+            tracing::warn_span!(target: "elsewhere", "by level").in_scope(|| {});
+        });
+        assert_eq!(vec!["by target", "by level"], names);
+    }
+
+    #[test]
+    fn reload_filter() {
+        let spans: Spans = Default::default();
+        let (subscriber, handle) = ActionTraceSubscriber::new_reloadable(
+            LevelFilter::WARN.into(),
+            TestSink {
+                spans: spans.clone(),
+            },
+            AlwaysNewSpanConstructor,
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::warn_span!("outer");
+            let _guard = outer.enter();
+            tracing::debug_span!("before reload").in_scope(|| {});
+
+            handle.reload("debug".parse().expect("filter should parse"));
+            tracing::debug_span!("after reload").in_scope(|| {});
+
+            handle.set_level(LevelFilter::INFO);
+            tracing::debug_span!("after set_level").in_scope(|| {});
+        });
+        assert_eq!(
+            LevelFilter::INFO,
+            handle.with_current(|filter| filter.max_level())
+        );
+
+        let spans = spans.lock().expect("local mutex");
+        let names: Vec<&str> = spans
+            .iter()
+            .map(|span| span.metadata.expect("there is metadata").name())
+            .collect();
+        assert_eq!(vec!["after reload", "outer"], names);
+    }
+
+    #[test]
+    fn field_directives() {
+        let names = span_names("warn,[request{user=bob}]=debug", || {
+            tracing::info_span!("request", user = "alice").in_scope(|| {
+                tracing::debug_span!("alice inner").in_scope(|| {});
+            });
+            tracing::info_span!("request", user = "bob").in_scope(|| {
+                tracing::debug_span!("bob inner").in_scope(|| {});
+                tracing::trace_span!("too verbose").in_scope(|| {});
+            });
+            tracing::debug_span!("outside").in_scope(|| {});
+        });
+        assert_eq!(vec!["request", "bob inner", "request"], names);
+    }
+
+    struct DropRequests;
+
+    impl Sampler for DropRequests {
+        fn should_sample(&self, parameters: &SamplingParameters<'_>) -> SamplingDecision {
+            if parameters.metadata.name() == "request" {
+                SamplingDecision::Drop
+            } else {
+                SamplingDecision::RecordAndSample
+            }
+        }
+    }
+
+    #[test]
+    fn unsampled_span_scope_ends_with_last_handle() {
+        let spans: Spans = Default::default();
+        let subscriber = test_subscriber_with(&spans, AlwaysNewSpanConstructor)
+            .with_filter(
+                "warn,[request{user=bob}]=debug"
+                    .parse()
+                    .expect("filter should parse"),
+            )
+            .with_sampler(DropRequests);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn_span!("request", user = "bob").in_scope(|| {
+                drop(tracing::Span::current());
+                tracing::debug_span!("bob inner").in_scope(|| {});
+            });
+            tracing::debug_span!("leaked scope").in_scope(|| {});
+        });
+        let spans = spans.lock().expect("local mutex");
+        let names: Vec<_> = spans
+            .iter()
+            .map(|span| span.metadata.expect("there is metadata").name())
+            .collect();
+        assert_eq!(Vec::<&str>::new(), names);
+    }
+}
@@ -14,7 +14,7 @@ pub type ChannelType = hyper::Client<
 /// ```rust
 ///  || {
 ///     let mut store = tokio_rustls::rustls::RootCertStore::empty();
-///     store.add_server_trust_anchors(
+///     store.add_trust_anchors(
 ///         webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|trust_anchor| {
 ///             tokio_rustls::rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
 ///                 trust_anchor.subject,
@@ -79,7 +79,7 @@ pub fn insecure_trust_store() -> Option<RootCertStore> {
 
 pub fn default_trust_store() -> Option<RootCertStore> {
     let mut store = tokio_rustls::rustls::RootCertStore::empty();
-    store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|trust_anchor| {
+    store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|trust_anchor| {
         tokio_rustls::rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
             trust_anchor.subject,
             trust_anchor.spki,
@@ -59,8 +59,10 @@ mod proto;
 
 mod channel_connection;
 mod header_interceptor;
+mod metadata_propagation;
 mod otlp_action_trace_sink;
 mod proto_conversions;
 
 pub use header_interceptor::header_interceptor;
+pub use metadata_propagation::{MetadataExtractor, MetadataInjector};
 pub use otlp_action_trace_sink::{OtlpActionTraceSink, OtlpAttributes, RequestInterceptor};
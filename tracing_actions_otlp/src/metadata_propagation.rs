@@ -0,0 +1,37 @@
+use tonic::metadata::{AsciiMetadataKey, AsciiMetadataValue, MetadataMap};
+use tracing_actions::propagation::{Extractor, Injector};
+
+/// Lets `tracing_actions::propagation::inject()` write trace context to outgoing grpc request metadata.
+/// ```rust
+/// use tracing_actions::{propagation, ActionSpanExt};
+/// use tracing_actions_otlp::MetadataInjector;
+///
+/// let mut request = tonic::Request::new(());
+/// if let Some(context) = tracing::Span::current().span_context() {
+///     propagation::inject(&context, &mut MetadataInjector(request.metadata_mut()));
+/// }
+/// ```
+pub struct MetadataInjector<'a>(pub &'a mut MetadataMap);
+
+impl<'a> Injector for MetadataInjector<'a> {
+    fn set(&mut self, key: &str, value: String) {
+        match (
+            AsciiMetadataKey::from_bytes(key.as_bytes()),
+            AsciiMetadataValue::try_from(value),
+        ) {
+            (Ok(key), Ok(value)) => {
+                self.0.insert(key, value);
+            }
+            _ => log::debug!("could not inject metadata {key}"),
+        }
+    }
+}
+
+/// Lets `tracing_actions::propagation::extract()` read trace context from incoming grpc request metadata.
+pub struct MetadataExtractor<'a>(pub &'a MetadataMap);
+
+impl<'a> Extractor for MetadataExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+}
@@ -1,7 +1,10 @@
 use std::{
     error::Error,
     mem,
-    sync::{Arc, Mutex, MutexGuard},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
 };
 
 use tracing_actions::TraceSink;
@@ -39,12 +42,40 @@ pub struct OtlpActionTraceSink {
     batch: Mutex<Vec<Span>>,
     batch_size: usize,
     attributes: OtlpAttributes,
+    counters: Arc<ExportCounters>,
+}
+
+/// Outcomes of the batches sent so far. They are updated from the tasks that send them.
+#[derive(Debug, Default)]
+struct ExportCounters {
+    export_successes: AtomicU64,
+    export_failures: AtomicU64,
+    rejected_spans: AtomicU64,
 }
 
 impl TraceSink for OtlpActionTraceSink {
     fn sink_trace(&self, trace: &mut tracing_actions::ActionSpan) {
         self.send(trace)
     }
+
+    /// `export_successes` and `export_failures` count batches. `rejected_spans` counts the spans
+    /// the server reported as rejected in batches that partially succeeded.
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            (
+                "export_successes",
+                self.counters.export_successes.load(Ordering::Relaxed),
+            ),
+            (
+                "export_failures",
+                self.counters.export_failures.load(Ordering::Relaxed),
+            ),
+            (
+                "rejected_spans",
+                self.counters.rejected_spans.load(Ordering::Relaxed),
+            ),
+        ]
+    }
 }
 
 impl OtlpActionTraceSink {
@@ -69,12 +100,13 @@ impl OtlpActionTraceSink {
             batch_size,
             batch: Mutex::new(Vec::with_capacity(batch_size)),
             attributes,
+            counters: Default::default(),
         })
     }
 
     pub fn drain_batch(&self) {
         let spans = self.batch.lock().expect("lock should not be poisoned");
-        if spans.len() == 0 {
+        if spans.is_empty() {
             return;
         }
         self.send_batch(spans)
@@ -92,6 +124,7 @@ impl OtlpActionTraceSink {
             batch_client,
             batch_interceptors,
             self.attributes.clone(),
+            self.counters.clone(),
         ));
     }
 
@@ -145,6 +178,7 @@ async fn send_batch(
     mut client: TraceServiceClient<ChannelType>,
     interceptors: Arc<Option<Box<dyn RequestInterceptor>>>,
     attributes: OtlpAttributes,
+    counters: Arc<ExportCounters>,
 ) {
     let mut request = tonic::Request::new(ExportTraceServiceRequest {
         resource_spans: vec![ResourceSpans {
@@ -171,6 +205,7 @@ async fn send_batch(
     }
     match client.export(request).await {
         Ok(response) => {
+            counters.export_successes.fetch_add(1, Ordering::Relaxed);
             if !response.metadata().is_empty() {
                 log::info!(
                     "received metadata from trace request: {:?}",
@@ -179,10 +214,14 @@ async fn send_batch(
             }
             let inner = response.into_inner();
             if let Some(partial) = inner.partial_success {
+                counters
+                    .rejected_spans
+                    .fetch_add(partial.rejected_spans.max(0) as u64, Ordering::Relaxed);
                 log::warn!("partial trace report: {partial:#?}")
             }
         }
         Err(error) => {
+            counters.export_failures.fetch_add(1, Ordering::Relaxed);
             log::error!("failed to send traces: {error:?}")
         }
     }
@@ -1,4 +1,5 @@
 #[allow(clippy::enum_variant_names)] // redundantredundant opentelemetry enumenums left as is as is
+#[allow(dead_code)] // not every generated message is used
 pub mod opentelemetry {
     pub mod collector {
         pub mod trace {
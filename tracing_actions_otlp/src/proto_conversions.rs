@@ -1,12 +1,12 @@
 use std::time::SystemTime;
 
-use tracing_actions::{ActionEvent, AttributeValue, SpanStatus, TraceKind};
+use tracing_actions::{ActionEvent, ActionLink, AttributeValue, SpanStatus, TraceKind};
 
 use crate::{
     proto::opentelemetry::{
         common::v1::{any_value, AnyValue, KeyValue},
         trace::v1::{
-            span::{self, Event},
+            span::{self, Event, Link},
             status::StatusCode,
             Span, Status,
         },
@@ -39,6 +39,48 @@ impl From<OtlpAttributes> for Vec<KeyValue> {
 
 impl From<&mut tracing_actions::ActionSpan> for Span {
     fn from(value: &mut tracing_actions::ActionSpan) -> Self {
+        // The span's own measurements go first, so a span full of attributes still has them.
+        let mut attributes = vec![
+            KeyValue::from(("busy_ns", AttributeValue::U64(value.busy.as_nanos() as u64))),
+            KeyValue::from(("idle_ns", AttributeValue::U64(value.idle.as_nanos() as u64))),
+            KeyValue::from(("enter_count", AttributeValue::U64(value.enter_count))),
+            KeyValue::from((
+                "longest_poll_ns",
+                AttributeValue::U64(value.longest_poll.as_nanos() as u64),
+            )),
+        ];
+        if value.slow_poll {
+            attributes.push(KeyValue::from(("slow_poll", AttributeValue::Bool(true))));
+        }
+        if let Some(cpu_time) = value.cpu_time {
+            attributes.push(KeyValue::from((
+                "cpu_time_ns",
+                AttributeValue::U64(cpu_time.as_nanos() as u64),
+            )));
+        }
+        if value.allocations != 0 {
+            attributes.extend([
+                KeyValue::from(("allocations", AttributeValue::U64(value.allocations))),
+                KeyValue::from((
+                    "allocated_bytes",
+                    AttributeValue::U64(value.allocated_bytes),
+                )),
+            ]);
+        }
+        if value.truncated {
+            attributes.push(KeyValue::from(("truncated", AttributeValue::Bool(true))));
+        }
+        if value.parent_is_remote {
+            // This version of the otlp protocol has no span flags, so the remote parent bit is an attribute.
+            attributes.push(KeyValue::from((
+                "parent.is_remote",
+                AttributeValue::Bool(true),
+            )));
+        }
+        attributes.extend(value.attributes.drain().map(KeyValue::from));
+        let max_attributes = value.limits.max_attributes_per_span;
+        let over_limit = attributes.len().saturating_sub(max_attributes);
+        attributes.truncate(max_attributes);
         Self {
             trace_id: value.trace_id.to_vec(),
             span_id: value.span_id.to_vec(),
@@ -48,9 +90,9 @@ impl From<&mut tracing_actions::ActionSpan> for Span {
                 .map(|id| id.to_vec())
                 .unwrap_or_default(),
             name: value
-                .metadata
-                .map(|m| m.name().to_string())
-                .unwrap_or_else(|| "unknown".to_string()),
+                .name_override
+                .take()
+                .unwrap_or_else(|| value.name().to_string()),
             kind: as_spankind(value.kind) as i32,
             start_time_unix_nano: value
                 .start
@@ -62,13 +104,13 @@ impl From<&mut tracing_actions::ActionSpan> for Span {
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_nanos() as u64,
-            attributes: value.attributes.drain().map(KeyValue::from).collect(),
-            dropped_attributes_count: 0,
+            attributes,
+            dropped_attributes_count: value.dropped_attributes_count + over_limit as u32,
             events: value.events.drain(..).map(Event::from).collect(),
-            dropped_events_count: 0,
-            links: vec![],
-            dropped_links_count: 0,
-            status: Some(value.status.into()),
+            dropped_events_count: value.dropped_events_count,
+            links: value.links.drain(..).map(Link::from).collect(),
+            dropped_links_count: value.dropped_links_count,
+            status: Some((value.status, std::mem::take(&mut value.status_message)).into()),
         }
     }
 }
@@ -77,6 +119,9 @@ fn as_spankind(value: TraceKind) -> span::SpanKind {
     match value {
         TraceKind::Client => span::SpanKind::Client,
         TraceKind::Server => span::SpanKind::Server,
+        TraceKind::Internal => span::SpanKind::Internal,
+        TraceKind::Producer => span::SpanKind::Producer,
+        TraceKind::Consumer => span::SpanKind::Consumer,
     }
 }
 
@@ -111,20 +156,38 @@ impl From<ActionEvent> for Event {
                 .as_nanos() as u64,
             name: value.metadata.name().to_string(),
             attributes: value.attributes.drain().map(KeyValue::from).collect(),
-            dropped_attributes_count: 0,
+            dropped_attributes_count: value.dropped_attributes_count,
         }
     }
 }
 
-impl From<SpanStatus> for Status {
-    fn from(value: SpanStatus) -> Self {
-        match value {
+impl From<ActionLink> for Link {
+    fn from(mut value: ActionLink) -> Self {
+        Self {
+            trace_id: value.trace_id.to_vec(),
+            span_id: value.span_id.to_vec(),
+            trace_state: value.trace_state,
+            attributes: value.attributes.drain().map(KeyValue::from).collect(),
+            dropped_attributes_count: value.dropped_attributes_count,
+        }
+    }
+}
+
+impl From<(SpanStatus, String)> for Status {
+    fn from(value: (SpanStatus, String)) -> Self {
+        let (status, message) = value;
+        match status {
+            SpanStatus::Unset => Self {
+                message: "".to_string(),
+                code: StatusCode::Unset.into(),
+            },
             SpanStatus::Ok => Self {
                 message: "".to_string(),
                 code: StatusCode::Ok.into(),
             },
+            // Only errors have a description in otlp
             SpanStatus::Error => Self {
-                message: "".to_string(),
+                message,
                 code: StatusCode::Error.into(),
             },
         }